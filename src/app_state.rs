@@ -1,27 +1,418 @@
-use crate::drawing::{Color, Command, DrawingCanvas, ShapeProperties, ToolMode};
+use crate::color_space::Hsv;
+use crate::drawing::{
+    element_size, now, save_text_file, Canvas, CanvasProperties, Color, Command, Connectivity,
+    DrawingCanvas, FillMode, PixelPatch, RasterPatch, ShapeProperties, ToolMode, Transformation,
+    LINE_WIDTHS,
+};
+use crate::file_format::{Document, FILE_EXTENSION};
+use crate::history::{History, HistoryBudget, NodeId};
+use crate::keymap::{Action, Keymap};
+use crate::layers::{Layer, LayerCommand, LayerId};
+use crate::palette::Palette;
+use crate::viewport::{Viewport, VIEWPORT_ID, ZOOM_STEP};
 use dioxus::prelude::*;
 use dioxus_elements::geometry::ElementPoint;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct AppState {
     pub current_point: Signal<ElementPoint>,
     pub tool_mode: Signal<ToolMode>,
     pub tool_active: Signal<bool>,
 
-    pub undo_commands: Signal<Vec<Command>>,
-    pub redo_commands: Signal<Vec<Command>>,
+    pub history: Signal<History>,
+    pub history_budget: Signal<HistoryBudget>,
     pub current_path: Signal<Vec<ElementPoint>>,
 
     pub rgb_color: Signal<Color>,
-    pub hue: Signal<f64>,
-    pub sat_val_coord: Signal<(f64, f64)>,
+    /// Where the picker shows `rgb_color`. Change the two together through
+    /// `set_color` and `set_hsv`.
+    pub hsv: Signal<Hsv>,
+    /// The background color, used to fill closed shapes.
+    pub fill_color: Signal<Color>,
+    pub fill_mode: Signal<FillMode>,
+    pub palette: Signal<Palette>,
 
     pub line_width: Signal<f64>,
 
+    /// Paint bucket settings.
+    pub bucket_tolerance: Signal<u8>,
+    pub bucket_connectivity: Signal<Connectivity>,
+    /// The eyedropper averages a square this many pixels wide: 1, 3 or 5.
+    pub eyedropper_size: Signal<u32>,
+    /// What the selection tool has picked on the active layer, as positions
+    /// among the layer's history entries.
+    pub selection: Signal<Vec<usize>>,
+    /// The closed outline of the marquee or lasso selection on the active
+    /// layer, empty when there is none.
+    pub pixel_selection: Signal<Vec<ElementPoint>>,
+    /// The pixels last cut or copied, with the outline they were cut along.
+    pub clipboard: Signal<Option<(Vec<ElementPoint>, PixelPatch)>>,
+
     pub canvas_cursor: Signal<String>,
     pub point_down: Signal<ElementPoint>,
 
     pub drawing_canvas: Signal<DrawingCanvas>,
+    /// Change it through `set_viewport`, which keeps the canvas scale in step.
+    pub viewport: Signal<Viewport>,
+    /// Space is held down, which makes dragging the canvas pan it.
+    pub space_held: Signal<bool>,
 
     pub shape_properties: Signal<ShapeProperties>,
+
+    /// Bottom to top.
+    pub layers: Signal<Vec<Layer>>,
+    pub active_layer: Signal<LayerId>,
+
+    pub keymap: Signal<Keymap>,
+}
+
+impl AppState {
+    /// The canvas of the layer that tools draw on.
+    pub fn active_canvas(&self) -> DrawingCanvas {
+        self.drawing_canvas
+            .read()
+            .for_layer(*self.active_layer.read())
+    }
+
+    /// A hidden canvas the size of the drawing, drawn at its own resolution
+    /// whatever the zoom, for work that shouldn't show.
+    pub fn scratch_canvas(&self) -> DrawingCanvas {
+        let canvas = *self.drawing_canvas.read();
+        DrawingCanvas {
+            properties: CanvasProperties {
+                name: "scratch-canvas",
+                ..canvas.properties
+            },
+            layer: None,
+            scale: 1.0,
+        }
+    }
+
+    /// A hidden canvas like a layer's, so the history's checkpoints fit it,
+    /// to draw thumbnails on.
+    pub fn thumbnail_canvas(&self) -> DrawingCanvas {
+        let canvas = *self.drawing_canvas.read();
+        DrawingCanvas {
+            properties: CanvasProperties {
+                name: "thumbnail-canvas",
+                ..canvas.properties
+            },
+            layer: None,
+            ..canvas
+        }
+    }
+
+    pub fn active_layer_editable(&self) -> bool {
+        let active_layer = *self.active_layer.read();
+        self.layers
+            .read()
+            .iter()
+            .any(|layer| layer.id == active_layer && layer.is_editable())
+    }
+
+    pub fn select_tool(&mut self, tool: ToolMode) {
+        self.tool_mode.set(tool);
+        if let Some(cursor) = tool.cursor() {
+            self.canvas_cursor.set(cursor.to_string());
+        }
+    }
+
+    /// What a keyboard shortcut does.
+    pub fn run(&mut self, action: Action) {
+        let line_width = *self.line_width.peek();
+        match action {
+            Action::Undo => self.undo(),
+            Action::Redo => self.redo(),
+            Action::ClearCanvas => self.clear_active_layer(),
+            Action::Save => self.save_document(),
+            Action::Tool(tool) => self.select_tool(tool),
+            Action::ThinnerLine => self
+                .line_width
+                .set((line_width - 1.0).max(*LINE_WIDTHS.start())),
+            Action::ThickerLine => self
+                .line_width
+                .set((line_width + 1.0).min(*LINE_WIDTHS.end())),
+            Action::Cut => self.cut_pixels(),
+            Action::Copy => self.copy_pixels(),
+            Action::Paste => self.paste_pixels(),
+            Action::DeleteSelection => self.delete_pixels(),
+            Action::NudgeLeft => self.nudge(-1, 0),
+            Action::NudgeRight => self.nudge(1, 0),
+            Action::NudgeUp => self.nudge(0, -1),
+            Action::NudgeDown => self.nudge(0, 1),
+            Action::ZoomIn => self.zoom_by(ZOOM_STEP),
+            Action::ZoomOut => self.zoom_by(1.0 / ZOOM_STEP),
+            Action::ZoomToFit => self.zoom_to_fit(),
+            Action::ActualSize => self.zoom_to_actual_size(),
+        }
+    }
+
+    /// Shows the drawing through `viewport`. The layers are drawn again once
+    /// the zoom calls for sharper or coarser canvases.
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        let canvas = *self.drawing_canvas.peek();
+        let scale = viewport.canvas_scale(canvas.properties.width, canvas.properties.height);
+        if scale != canvas.scale {
+            // setting the canvas redraws it
+            self.drawing_canvas.set(DrawingCanvas { scale, ..canvas });
+        }
+        self.viewport.set(viewport);
+    }
+
+    /// Zooms in or out by `factor`, about the middle of the canvas area.
+    pub fn zoom_by(&mut self, factor: f64) {
+        let viewport = *self.viewport.peek();
+        let (width, height) = element_size(VIEWPORT_ID).unwrap_or_default();
+        let middle = ElementPoint::new(width / 2.0, height / 2.0);
+        self.set_viewport(viewport.zoomed(viewport.zoom * factor, middle));
+    }
+
+    /// One screen pixel per pixel of the drawing.
+    pub fn zoom_to_actual_size(&mut self) {
+        let zoom = self.viewport.peek().zoom;
+        self.zoom_by(1.0 / zoom);
+    }
+
+    /// Shows the whole drawing, as large as it fits.
+    pub fn zoom_to_fit(&mut self) {
+        let properties = self.drawing_canvas.peek().properties;
+        if let Some((width, height)) = element_size(VIEWPORT_ID) {
+            self.set_viewport(Viewport::fit(
+                properties.width,
+                properties.height,
+                width,
+                height,
+            ));
+        }
+    }
+
+    pub fn undo(&mut self) {
+        let layer = self.history.write().undo().map(|entry| entry.layer);
+        if let Some(layer) = layer {
+            self.redraw_layer(layer);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        let entry = self.history.write().redo().cloned();
+        if let Some(LayerCommand { layer, command }) = entry {
+            match command.targets().is_empty() {
+                true => command.execute(&mut self.drawing_canvas.peek().for_layer(layer)),
+                // it changes earlier commands, so the layer has to be replayed
+                false => self.redraw_layer(layer),
+            }
+        }
+    }
+
+    /// Goes to any state in the history, on the current branch or not.
+    pub fn jump_to(&mut self, node: Option<NodeId>) {
+        let changed = self.history.write().jump(node);
+        changed
+            .into_iter()
+            .for_each(|layer| self.redraw_layer(layer));
+    }
+
+    /// Clearing only affects the active layer.
+    pub fn clear_active_layer(&mut self) {
+        if !self.active_layer_editable() {
+            return;
+        }
+        self.active_canvas().clear_canvas();
+        let active_layer = *self.active_layer.peek();
+        let already_clear = self
+            .history
+            .peek()
+            .undo_commands()
+            .iter()
+            .rfind(|entry| entry.layer == active_layer)
+            .is_some_and(|entry| entry.command == Command::EraseCanvas);
+        if !already_clear {
+            self.record(Command::EraseCanvas)
+        };
+    }
+
+    pub fn save_document(&self) {
+        let document = Document::new(
+            self.drawing_canvas.peek().properties,
+            self.layers.peek().clone(),
+            self.history.peek().saved(),
+        );
+        save_text_file(
+            &document.to_json(),
+            "application/json",
+            &format!("drawing.{}", FILE_EXTENSION),
+        );
+    }
+
+    /// Sets the drawing color and moves the picker to it.
+    pub fn set_color(&mut self, color: Color) {
+        let previous = *self.hsv.peek();
+        self.hsv.set(Hsv::from_rgb(color, previous));
+        self.rgb_color.set(color);
+    }
+
+    /// Sets the drawing color from the picker, keeping its alpha.
+    pub fn set_hsv(&mut self, hsv: Hsv) {
+        let alpha = self.rgb_color.peek().3;
+        self.hsv.set(hsv);
+        self.rgb_color.set(hsv.to_rgb(alpha));
+    }
+
+    /// Adds a command drawn on the active layer to the history, which
+    /// forgets what was undone.
+    pub fn record(&mut self, command: Command) {
+        let layer = *self.active_layer.peek();
+        self.history.write().record(LayerCommand { layer, command });
+        self.compact_history();
+    }
+
+    /// Like `record`, but a quick run of commands of the same `kind` joins
+    /// into one entry.
+    pub fn record_coalesced(&mut self, command: Command, kind: &'static str) {
+        let layer = *self.active_layer.peek();
+        self.history
+            .write()
+            .record_coalesced(LayerCommand { layer, command }, kind, now());
+        self.compact_history();
+    }
+
+    /// What's recorded from here until `end_transaction` undoes in one step.
+    pub fn begin_transaction(&mut self) {
+        self.history.write().begin_transaction();
+    }
+
+    pub fn end_transaction(&mut self) {
+        self.history.write().end_transaction();
+        self.compact_history();
+    }
+
+    /// Flattens the oldest history into images once it's over the budget.
+    pub fn compact_history(&mut self) {
+        let budget = *self.history_budget.peek();
+        if !self.history.peek().over_budget(budget) {
+            return;
+        }
+        let mut scratch = self.scratch_canvas();
+        let flattened = self.history.write().compact(budget, &mut scratch);
+        flattened
+            .iter()
+            .for_each(|(layer, _)| self.redraw_layer(*layer));
+
+        // the selection refers to positions, which flattening moves
+        let active_layer = *self.active_layer.peek();
+        if let Some((_, count)) = flattened.iter().find(|(layer, _)| *layer == active_layer) {
+            let selection: Vec<usize> = self
+                .selection
+                .peek()
+                .iter()
+                .filter(|index| **index >= *count)
+                .map(|index| index - (count - 1))
+                .collect();
+            self.selection.set(selection);
+        }
+    }
+
+    /// Moves the selection of the selection tool, or the pixels of the marquee
+    /// or lasso selection, by a pixel or so.
+    pub fn nudge(&mut self, dx: i32, dy: i32) {
+        if !self.active_layer_editable() {
+            return;
+        }
+        let offset = euclid::vec2(dx as f64, dy as f64);
+        let tool = *self.tool_mode.peek();
+        match tool {
+            ToolMode::Select => {
+                let selection = self.selection.peek().clone();
+                if selection.is_empty() {
+                    return;
+                }
+                self.record_coalesced(
+                    Command::Transform(Transformation {
+                        targets: selection,
+                        center: ElementPoint::zero(),
+                        scale: 1.0,
+                        rotation: 0.0,
+                        offset,
+                    }),
+                    "nudge selection",
+                );
+                self.redraw_layer(*self.active_layer.peek());
+            }
+            ToolMode::Marquee | ToolMode::Lasso => {
+                let outline = self.pixel_selection.peek().clone();
+                let Some(pixels) = self.active_canvas().copy_pixels(&outline) else {
+                    return;
+                };
+                let command = Command::Patch(RasterPatch {
+                    cleared: outline.clone(),
+                    pasted: Some(pixels.moved(dx, dy)),
+                });
+                self.active_canvas().draw_command(&command);
+                self.record_coalesced(command, "nudge pixels");
+                self.pixel_selection
+                    .set(outline.iter().map(|point| *point + offset).collect());
+            }
+            _ => {}
+        }
+    }
+
+    /// Draws and records `patch` on the active layer, unless it is locked or hidden.
+    pub fn apply_patch(&mut self, patch: RasterPatch) {
+        if !self.active_layer_editable() {
+            return;
+        }
+        let command = Command::Patch(patch);
+        self.active_canvas().draw_command(&command);
+        self.record(command);
+    }
+
+    pub fn copy_pixels(&mut self) {
+        let outline = self.pixel_selection.peek().clone();
+        if let Some(pixels) = self.active_canvas().copy_pixels(&outline) {
+            self.clipboard.set(Some((outline, pixels)));
+        }
+    }
+
+    pub fn cut_pixels(&mut self) {
+        self.copy_pixels();
+        self.delete_pixels();
+    }
+
+    pub fn delete_pixels(&mut self) {
+        let outline = self.pixel_selection.peek().clone();
+        if !outline.is_empty() {
+            self.apply_patch(RasterPatch {
+                cleared: outline,
+                pasted: None,
+            });
+        }
+    }
+
+    /// Pastes the clipboard where it was copied from and selects it, ready
+    /// to be dragged into place.
+    pub fn paste_pixels(&mut self) {
+        let Some((outline, pixels)) = self.clipboard.peek().clone() else {
+            return;
+        };
+        self.apply_patch(RasterPatch {
+            cleared: vec![],
+            pasted: Some(pixels),
+        });
+        self.pixel_selection.set(outline);
+    }
+
+    // Redrawing doesn't subscribe to the history, so it is safe inside effects.
+
+    /// Redraws one layer out of the history.
+    pub fn redraw_layer(&self, layer: LayerId) {
+        let mut canvas = self.drawing_canvas.peek().for_layer(layer);
+        self.history.peek().render_layer(&mut canvas, layer);
+    }
+
+    pub fn redraw_all_layers(&self) {
+        self.layers
+            .peek()
+            .iter()
+            .for_each(|layer| self.redraw_layer(layer.id));
+    }
 }
@@ -0,0 +1,361 @@
+// color_space.rs
+//
+// Conversions between the color models the app works in. `Color` (RGB from 0
+// to 255 plus alpha) is what gets drawn, the picker is laid out in HSV, and
+// HSL and hex are ways of writing a color down. The picker computes colors
+// through these functions in both directions rather than reading pixels back
+// from its canvases.
+
+use crate::drawing::Color;
+
+/// Hue in degrees from 0 up to 360, saturation and value from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hsv {
+    pub hue: f64,
+    pub saturation: f64,
+    pub value: f64,
+}
+
+/// Hue in degrees from 0 up to 360, saturation and lightness from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hsl {
+    pub hue: f64,
+    pub saturation: f64,
+    pub lightness: f64,
+}
+
+impl Hsv {
+    pub fn to_rgb(self, alpha: f64) -> Color {
+        let chroma = self.value * self.saturation;
+        from_chroma(self.hue, chroma, self.value - chroma, alpha)
+    }
+
+    /// Greys have no hue of their own, so they keep `hue` instead of
+    /// snapping back to red. Likewise black keeps `previous`'s saturation.
+    pub fn from_rgb(color: Color, previous: Hsv) -> Self {
+        let (max, min) = max_min(color);
+        let chroma = max - min;
+        Self {
+            hue: rgb_hue(color, max, chroma).unwrap_or(previous.hue),
+            saturation: match max {
+                0.0 => previous.saturation,
+                _ => chroma / max,
+            },
+            value: max,
+        }
+    }
+}
+
+impl Hsl {
+    pub fn to_rgb(self, alpha: f64) -> Color {
+        let chroma = (1.0 - (2.0 * self.lightness - 1.0).abs()) * self.saturation;
+        from_chroma(self.hue, chroma, self.lightness - chroma / 2.0, alpha)
+    }
+
+    pub fn from_rgb(color: Color) -> Self {
+        let (max, min) = max_min(color);
+        let chroma = max - min;
+        let lightness = (max + min) / 2.0;
+        let saturation = match lightness {
+            0.0 | 1.0 => 0.0,
+            _ => chroma / (1.0 - (2.0 * lightness - 1.0).abs()),
+        };
+        Self {
+            hue: rgb_hue(color, max, chroma).unwrap_or(0.0),
+            saturation,
+            lightness,
+        }
+    }
+}
+
+/// `#rrggbb`, or `#rrggbbaa` when the color isn't opaque.
+pub fn to_hex(color: Color) -> String {
+    let byte = |value: f64| value.round().clamp(0.0, 255.0) as u8;
+    let rgb = format!(
+        "#{:02x}{:02x}{:02x}",
+        byte(color.0),
+        byte(color.1),
+        byte(color.2)
+    );
+    match color.3 < 1.0 {
+        true => format!("{}{:02x}", rgb, byte(color.3 * 255.0)),
+        false => rgb,
+    }
+}
+
+/// Reads `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`, with or without the `#`.
+pub fn from_hex(hex: &str) -> Result<Color, String> {
+    let digits = hex.trim().trim_start_matches('#');
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "'{}' has characters that aren't hex digits",
+            hex.trim()
+        ));
+    }
+    let channel = |i: usize, width: usize| {
+        let value = u8::from_str_radix(&digits[i * width..(i + 1) * width], 16).unwrap();
+        // a single digit stands for itself repeated, so f means ff
+        match width {
+            1 => value * 17,
+            _ => value,
+        }
+    };
+    let (count, width) = match digits.len() {
+        3 => (3, 1),
+        4 => (4, 1),
+        6 => (3, 2),
+        8 => (4, 2),
+        _ => return Err("hex colors have 3, 4, 6 or 8 digits".to_string()),
+    };
+    let alpha = match count {
+        4 => channel(3, width) as f64 / 255.0,
+        _ => 1.0,
+    };
+    Ok(Color(
+        channel(0, width) as f64,
+        channel(1, width) as f64,
+        channel(2, width) as f64,
+        alpha,
+    ))
+}
+
+// PRIVATE
+
+/// The RGB channels scaled to 0..=1, largest and smallest.
+fn max_min(color: Color) -> (f64, f64) {
+    let channels = [color.0 / 255.0, color.1 / 255.0, color.2 / 255.0];
+    (
+        channels.iter().copied().fold(0.0, f64::max),
+        channels.iter().copied().fold(1.0, f64::min),
+    )
+}
+
+fn rgb_hue(color: Color, max: f64, chroma: f64) -> Option<f64> {
+    if chroma == 0.0 {
+        return None;
+    }
+    let (r, g, b) = (color.0 / 255.0, color.1 / 255.0, color.2 / 255.0);
+    let sector = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    Some(sector * 60.0)
+}
+
+/// The shared last step of HSV and HSL: `chroma` spread over the channels by
+/// `hue`, then `offset` added to all of them.
+fn from_chroma(hue: f64, chroma: f64, offset: f64, alpha: f64) -> Color {
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let second = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, second, 0.0),
+        1 => (second, chroma, 0.0),
+        2 => (0.0, chroma, second),
+        3 => (0.0, second, chroma),
+        4 => (second, 0.0, chroma),
+        _ => (chroma, 0.0, second),
+    };
+    Color(
+        (r + offset) * 255.0,
+        (g + offset) * 255.0,
+        (b + offset) * 255.0,
+        alpha,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Hsv = Hsv {
+        hue: 0.0,
+        saturation: 1.0,
+        value: 1.0,
+    };
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    fn assert_same_color(actual: Color, expected: Color) {
+        assert_near(actual.0, expected.0);
+        assert_near(actual.1, expected.1);
+        assert_near(actual.2, expected.2);
+        assert_near(actual.3, expected.3);
+    }
+
+    /// A spread of hues, greys and in-between colors, opaque and with an alpha
+    /// that a hex byte writes exactly.
+    fn colors() -> Vec<Color> {
+        let mut colors = vec![];
+        for r in [0.0, 51.0, 128.0, 255.0] {
+            for g in [0.0, 17.0, 200.0, 255.0] {
+                for b in [0.0, 99.0, 255.0] {
+                    colors.push(Color(r, g, b, 1.0));
+                    colors.push(Color(r, g, b, 0.4));
+                }
+            }
+        }
+        colors
+    }
+
+    #[test]
+    fn hsv_round_trips_through_rgb() {
+        for color in colors() {
+            let hsv = Hsv::from_rgb(color, RED);
+            assert_same_color(hsv.to_rgb(color.3), color);
+        }
+        let hsv = Hsv {
+            hue: 210.0,
+            saturation: 0.5,
+            value: 0.8,
+        };
+        let back = Hsv::from_rgb(hsv.to_rgb(1.0), RED);
+        assert_near(back.hue, hsv.hue);
+        assert_near(back.saturation, hsv.saturation);
+        assert_near(back.value, hsv.value);
+    }
+
+    #[test]
+    fn hsl_round_trips_through_rgb() {
+        for color in colors() {
+            assert_same_color(Hsl::from_rgb(color).to_rgb(color.3), color);
+        }
+        let hsl = Hsl {
+            hue: 300.0,
+            saturation: 0.25,
+            lightness: 0.6,
+        };
+        let back = Hsl::from_rgb(hsl.to_rgb(1.0));
+        assert_near(back.hue, hsl.hue);
+        assert_near(back.saturation, hsl.saturation);
+        assert_near(back.lightness, hsl.lightness);
+    }
+
+    #[test]
+    fn known_colors_convert() {
+        assert_same_color(RED.to_rgb(1.0), Color(255.0, 0.0, 0.0, 1.0));
+        let green = Hsl {
+            hue: 120.0,
+            saturation: 1.0,
+            lightness: 0.5,
+        };
+        assert_same_color(green.to_rgb(0.5), Color(0.0, 255.0, 0.0, 0.5));
+        let blue = Hsv::from_rgb(Color(0.0, 0.0, 255.0, 1.0), RED);
+        assert_eq!(blue.hue, 240.0);
+        // hues wrap around, so 360 is red again
+        let wrapped = Hsv { hue: 360.0, ..RED };
+        assert_same_color(wrapped.to_rgb(1.0), Color(255.0, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn greys_keep_the_previous_hue() {
+        let previous = Hsv {
+            hue: 123.0,
+            saturation: 0.7,
+            value: 0.4,
+        };
+        let grey = Hsv::from_rgb(Color(128.0, 128.0, 128.0, 1.0), previous);
+        assert_eq!(grey.hue, 123.0);
+        assert_eq!(grey.saturation, 0.0);
+        assert_near(grey.value, 128.0 / 255.0);
+
+        let white = Hsv::from_rgb(Color(255.0, 255.0, 255.0, 1.0), previous);
+        assert_eq!(
+            (white.hue, white.saturation, white.value),
+            (123.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn black_keeps_the_previous_hue_and_saturation() {
+        let previous = Hsv {
+            hue: 45.0,
+            saturation: 0.9,
+            value: 1.0,
+        };
+        let black = Hsv::from_rgb(Color(0.0, 0.0, 0.0, 1.0), previous);
+        assert_eq!(
+            black,
+            Hsv {
+                hue: 45.0,
+                saturation: 0.9,
+                value: 0.0,
+            }
+        );
+        // so dragging the value back up comes back to the same color
+        assert_same_color(
+            Hsv {
+                value: 1.0,
+                ..black
+            }
+            .to_rgb(1.0),
+            previous.to_rgb(1.0),
+        );
+    }
+
+    #[test]
+    fn hsl_greys_have_no_saturation() {
+        for value in [0.0, 64.0, 255.0] {
+            let grey = Hsl::from_rgb(Color(value, value, value, 1.0));
+            assert_eq!((grey.hue, grey.saturation), (0.0, 0.0));
+            assert_near(grey.lightness, value / 255.0);
+        }
+    }
+
+    #[test]
+    fn reads_every_length_of_hex() {
+        assert_eq!(from_hex("#f80"), Ok(Color(255.0, 136.0, 0.0, 1.0)));
+        assert_eq!(
+            from_hex("f808"),
+            Ok(Color(255.0, 136.0, 0.0, 136.0 / 255.0))
+        );
+        assert_eq!(from_hex("#12abEF"), Ok(Color(18.0, 171.0, 239.0, 1.0)));
+        assert_eq!(
+            from_hex("  #12abef80 "),
+            Ok(Color(18.0, 171.0, 239.0, 128.0 / 255.0))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_hex() {
+        for hex in ["", "#", "#12", "#12345", "#1234567", "#123456789"] {
+            assert_eq!(
+                from_hex(hex),
+                Err("hex colors have 3, 4, 6 or 8 digits".to_string()),
+                "{:?}",
+                hex
+            );
+        }
+        for hex in ["#ggg", "#12 456", "#12345z", "#ffé"] {
+            assert!(from_hex(hex).unwrap_err().contains("aren't hex digits"));
+        }
+    }
+
+    #[test]
+    fn writes_alpha_only_when_not_opaque() {
+        assert_eq!(to_hex(Color(18.0, 171.0, 239.0, 1.0)), "#12abef");
+        assert_eq!(to_hex(Color(18.0, 171.0, 239.0, 0.5)), "#12abef80");
+        assert_eq!(to_hex(Color(0.0, 0.0, 0.0, 0.0)), "#00000000");
+        // channels are rounded and clamped to a byte
+        assert_eq!(to_hex(Color(254.6, -3.0, 300.0, 1.0)), "#ff00ff");
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        for hex in ["#000000", "#ffffff", "#12abef", "#12abef80", "#00ff0001"] {
+            assert_eq!(to_hex(from_hex(hex).unwrap()), hex);
+        }
+        for color in colors() {
+            assert_same_color(from_hex(&to_hex(color)).unwrap(), color);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use crate::app_state::AppState;
+use crate::color_space::{from_hex, to_hex, Hsl, Hsv};
+use crate::drawing::Color;
+use dioxus::prelude::*;
+
+/// Typed-in entry of the drawing color, for when it has to be exact.
+#[allow(non_snake_case)]
+pub fn ColorFields() -> Element {
+    // SIGNALS
+    let mut app_state = use_context::<AppState>();
+    let rgb_color = use_context::<AppState>().rgb_color;
+    let hsv = use_context::<AppState>().hsv;
+
+    // HANDLERS
+    let hex_handler = move |text: String| {
+        app_state.set_color(from_hex(&text)?);
+        Ok(())
+    };
+    let rgb_handler = move |text: String| {
+        let [r, g, b] = parse_components(&text, [("R", 255.0), ("G", 255.0), ("B", 255.0)])?;
+        app_state.set_color(Color(r, g, b, rgb_color().3));
+        Ok(())
+    };
+    let hsv_handler = move |text: String| {
+        let [hue, saturation, value] =
+            parse_components(&text, [("H", 360.0), ("S", 100.0), ("V", 100.0)])?;
+        app_state.set_hsv(Hsv {
+            hue,
+            saturation: saturation / 100.0,
+            value: value / 100.0,
+        });
+        Ok(())
+    };
+    let hsl_handler = move |text: String| {
+        let [hue, saturation, lightness] =
+            parse_components(&text, [("H", 360.0), ("S", 100.0), ("L", 100.0)])?;
+        let hsl = Hsl {
+            hue,
+            saturation: saturation / 100.0,
+            lightness: lightness / 100.0,
+        };
+        app_state.set_color(hsl.to_rgb(rgb_color().3));
+        Ok(())
+    };
+
+    let color = rgb_color();
+    let hsl = Hsl::from_rgb(color);
+    rsx! {
+        div {
+            class: "color-fields",
+            ColorField {
+                label: "Hex",
+                value: to_hex(color),
+                onchange: hex_handler,
+            }
+            ColorField {
+                label: "RGB",
+                value: format!("{:.0}, {:.0}, {:.0}", color.0, color.1, color.2),
+                onchange: rgb_handler,
+            }
+            ColorField {
+                label: "HSV",
+                value: format!(
+                    "{:.0}, {:.0}%, {:.0}%",
+                    hsv().hue,
+                    hsv().saturation * 100.0,
+                    hsv().value * 100.0
+                ),
+                onchange: hsv_handler,
+            }
+            ColorField {
+                label: "HSL",
+                value: format!(
+                    "{:.0}, {:.0}%, {:.0}%",
+                    hsl.hue,
+                    hsl.saturation * 100.0,
+                    hsl.lightness * 100.0
+                ),
+                onchange: hsl_handler,
+            }
+        }
+    }
+}
+
+// PRIVATE
+
+/// A text field that applies its contents when committed, and says what was
+/// wrong with them underneath when they can't be.
+#[component]
+fn ColorField(
+    label: &'static str,
+    value: String,
+    onchange: Callback<String, Result<(), String>>,
+) -> Element {
+    let mut error = use_signal(|| None::<String>);
+
+    // the color changed, so whatever was typed before no longer applies
+    use_effect(use_reactive!(|value| {
+        let _ = value;
+        error.set(None);
+    }));
+
+    rsx! {
+        label {
+            class: "color-field-label",
+            "{label}"
+        }
+        input {
+            class: if error().is_some() { "color-field invalid" } else { "color-field" },
+            value: value,
+            onchange: move |event| error.set(onchange(event.value()).err()),
+        }
+        if let Some(message) = error() {
+            div {
+                class: "color-field-error",
+                "{message}"
+            }
+        }
+    }
+}
+
+/// Three comma separated numbers, each between 0 and its maximum. Percent
+/// signs and degree marks are allowed after them.
+fn parse_components(text: &str, components: [(&str, f64); 3]) -> Result<[f64; 3], String> {
+    let parts: Vec<&str> = text.split(',').map(str::trim).collect();
+    if parts.len() != 3 {
+        return Err("expected 3 numbers separated by commas".to_string());
+    }
+
+    let mut values = [0.0; 3];
+    for ((value, part), (name, max)) in values.iter_mut().zip(parts).zip(components) {
+        let number = part.trim_end_matches(['%', '°']).trim();
+        *value = number
+            .parse::<f64>()
+            .map_err(|_| format!("{} '{}' is not a number", name, part))?;
+        if !(0.0..=max).contains(value) {
+            return Err(format!("{} must be between 0 and {}", name, max));
+        }
+    }
+    Ok(values)
+}
@@ -1,6 +1,10 @@
 use crate::app_state::AppState;
+use crate::color_space::{to_hex, Hsv};
+use crate::components::ColorFields;
 use crate::drawing::*;
 use dioxus::prelude::*;
+use dioxus_free_icons::icons::ld_icons::LdArrowLeftRight;
+use dioxus_free_icons::Icon;
 
 #[allow(non_snake_case)]
 pub fn ColorPicker() -> Element {
@@ -21,32 +25,51 @@ pub fn ColorPicker() -> Element {
         },
     };
 
+    let alpha_canvas = AlphaCanvas {
+        properties: CanvasProperties {
+            name: "alpha-canvas",
+            width: sat_val_canvas.properties.width,
+            height: 20.0,
+        },
+    };
+
     // SIGNALS
+    let mut app_state = use_context::<AppState>();
     let mut rgb_color = use_context::<AppState>().rgb_color;
-    let mut hue = use_context::<AppState>().hue;
-    let mut sat_val_coord = use_context::<AppState>().sat_val_coord;
+    let hsv = use_context::<AppState>().hsv;
+    let mut fill_color = use_context::<AppState>().fill_color;
 
     // HANDLERS
-    let mut sat_val_click_handler = move |event: Event<MouseData>| {
-        sat_val_coord.set((
-            event.element_coordinates().x as f64,
-            event.element_coordinates().y as f64,
-        ));
-        rgb_color.set(sat_val_canvas.read_color(sat_val_coord()));
-
-        sat_val_canvas.clear_canvas();
-        sat_val_canvas.draw_color_picker(hue());
-
-        sat_val_canvas.draw_sat_val_pointer(sat_val_coord());
+    let sat_val_click_handler = move |event: Event<MouseData>| {
+        app_state.set_hsv(sat_val_canvas.hsv_at(event.element_coordinates(), hsv().hue));
     };
-    let mut hue_click_handler = move |event: Event<MouseData>| {
-        hue.set((event.element_coordinates().x / (sat_val_canvas.properties.width as f64)) * 360.0);
-        hue_canvas.draw_hue_pointer(hue());
-        sat_val_canvas.draw_color_picker(hue());
-
-        sat_val_canvas.draw_sat_val_pointer(sat_val_coord());
-        rgb_color.set(sat_val_canvas.read_color(sat_val_coord()));
+    let hue_click_handler = move |event: Event<MouseData>| {
+        app_state.set_hsv(Hsv {
+            hue: hue_canvas.hue_at(event.element_coordinates()),
+            ..hsv()
+        });
     };
+    let alpha_click_handler = move |event: Event<MouseData>| {
+        let alpha = event.element_coordinates().x / alpha_canvas.properties.width;
+        rgb_color.set(rgb_color().with_alpha(alpha.clamp(0.0, 1.0)));
+    };
+    // the picker always edits the foreground, so swapping is how the background gets picked
+    let swap_colors_handler = move |_event| {
+        let foreground = rgb_color();
+        app_state.set_color(fill_color());
+        fill_color.set(foreground);
+    };
+
+    // EFFECTS
+    // the pointers follow the color wherever it was set from
+    use_effect(move || {
+        let hsv = hsv();
+        sat_val_canvas.draw_color_picker(hsv.hue);
+        sat_val_canvas.draw_sat_val_pointer(hsv);
+        hue_canvas.draw_hue_bar(hsv.hue);
+    });
+    // the alpha bar shows the color it applies to
+    use_effect(move || alpha_canvas.draw_alpha_bar(rgb_color()));
 
     rsx! {
         div { // COLOR PICKER
@@ -55,12 +78,9 @@ pub fn ColorPicker() -> Element {
                 id: sat_val_canvas.properties.name,
                 width: sat_val_canvas.properties.width,
                 height: sat_val_canvas.properties.height,
-                onmounted: move |_event| {
-                    sat_val_canvas.draw_color_picker(hue());
-                    sat_val_canvas.draw_sat_val_pointer(sat_val_coord());
-                },
 
-                onclick: move |event| sat_val_click_handler(event),            }
+                onclick: sat_val_click_handler,
+            }
         }
 
         div {
@@ -68,10 +88,41 @@ pub fn ColorPicker() -> Element {
                 id: hue_canvas.properties.name,
                 width: hue_canvas.properties.width,
                 height: hue_canvas.properties.height,
-                onmounted: move |_event| hue_canvas.draw_hue_bar(hue()),
 
-                onclick: move |event| hue_click_handler(event),
+                onclick: hue_click_handler,
+            }
+        }
+
+        div {
+            canvas { // ALPHA BAR
+                id: alpha_canvas.properties.name,
+                width: alpha_canvas.properties.width,
+                height: alpha_canvas.properties.height,
 
+                onclick: alpha_click_handler,
+            }
+        }
+
+        ColorFields {}
+
+        div { // COLOR SLOTS
+            class: "color-slots",
+            div {
+                class: "color-swatch",
+                title: "Stroke color {to_hex(rgb_color())}",
+                background_color: rgb_color().to_css(),
+            }
+            div {
+                class: "color-swatch",
+                title: "Fill color {to_hex(fill_color())}",
+                background_color: fill_color().to_css(),
+            }
+            button {
+                title: "Swap stroke and fill colors",
+                onclick: swap_colors_handler,
+                Icon {
+                    icon: LdArrowLeftRight,
+                }
             }
         }
     }
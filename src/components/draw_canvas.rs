@@ -1,16 +1,37 @@
 // draw_canvas
 
 use crate::app_state::AppState;
+use crate::components::ZoomBar;
 use crate::drawing::*;
+use crate::geometry::{
+    command_at, commands_within, corner_handles, points_bounds, selected_commands,
+    transform_command, union_bounds, winding_number, SelectionDrag,
+};
+use crate::viewport::VIEWPORT_ID;
+use dioxus::html::geometry::WheelDelta;
+use dioxus::html::input_data::MouseButton;
 use dioxus::prelude::*;
+use dioxus_elements::geometry::ElementPoint;
 use enum_map::enum_map;
 use std::sync::{Arc, Mutex};
 
-#[allow(non_snake_case)]
+/// How close a click has to be to a polygon vertex to land on it, on screen.
+const CLOSE_DISTANCE: f64 = 6.0;
+/// How close a click with the selection tool has to be to a command to pick
+/// it, on screen.
+const SELECT_DISTANCE: f64 = 3.0;
+/// How much a pixel's worth of scrolling zooms, and a pixel's worth of
+/// pinching, which comes in much smaller steps.
+const WHEEL_ZOOM: f64 = 0.002;
+const PINCH_ZOOM: f64 = 0.01;
+
+#[allow(non_snake_case, clippy::arc_with_non_send_sync)]
 pub fn DrawCanvas() -> Element {
     // SETTING UP PROPERTIES
-    let drawing_canvas = use_context::<AppState>().drawing_canvas;
-    let drawing_canvas = drawing_canvas();
+    let mut app_state = use_context::<AppState>();
+    let canvas_signal = use_context::<AppState>().drawing_canvas;
+    // tools draw on the active layer
+    let drawing_canvas = app_state.active_canvas();
 
     let preview_canvas = DrawingCanvas {
         properties: CanvasProperties {
@@ -18,14 +39,16 @@ pub fn DrawCanvas() -> Element {
             width: drawing_canvas.properties.width,
             height: drawing_canvas.properties.height,
         },
+        layer: None,
+        scale: drawing_canvas.scale,
     };
+    let scratch_canvas = app_state.scratch_canvas();
+    let thumbnail_canvas = app_state.thumbnail_canvas();
 
     // SIGNALS
     let mut current_point = use_context::<AppState>().current_point;
     let tool_mode = use_context::<AppState>().tool_mode;
     let mut tool_active = use_context::<AppState>().tool_active;
-    let mut undo_commands = use_context::<AppState>().undo_commands;
-    let mut redo_commands = use_context::<AppState>().redo_commands;
     let mut current_path = use_context::<AppState>().current_path;
     let rgb_color = use_context::<AppState>().rgb_color;
     let canvas_cursor = use_context::<AppState>().canvas_cursor;
@@ -33,28 +56,241 @@ pub fn DrawCanvas() -> Element {
     let mut point_down = use_context::<AppState>().point_down;
 
     let line_width = use_context::<AppState>().line_width;
+    let fill_color = use_context::<AppState>().fill_color;
+    let fill_mode = use_context::<AppState>().fill_mode;
+    let bucket_tolerance = use_context::<AppState>().bucket_tolerance;
+    let bucket_connectivity = use_context::<AppState>().bucket_connectivity;
 
     let mut shape_properties = use_context::<AppState>().shape_properties;
 
+    let layers = use_context::<AppState>().layers;
+    let eyedropper_size = use_context::<AppState>().eyedropper_size;
+
+    let mut selection = use_context::<AppState>().selection;
+
+    let viewport = use_context::<AppState>().viewport;
+    let space_held = use_context::<AppState>().space_held;
+    // where the drag panning the view last was, on screen
+    let mut pan_from = use_signal(|| None::<ElementPoint>);
+
+    // the tool handling the current press, which Alt can make differ from tool_mode
+    let mut pressed_tool = use_signal(|| ToolMode::Pen);
+    // what the current drag with the selection tool is doing
+    let mut selection_drag = use_signal(|| SelectionDrag::Band);
+
+    let mut pixel_selection = use_context::<AppState>().pixel_selection;
+    // pixels lifted off the layer while the marquee or lasso selection is dragged
+    let mut floating = use_signal(|| None::<PixelPatch>);
+    // how far the marching ants have marched
+    let mut ants_phase = use_signal(|| 0.0);
+    use_hook(|| every(120, move || *ants_phase.write() += 1.0));
+
+    // EFFECTS
+    // switching tools abandons whatever the previous tool had in progress
+    use_effect(move || {
+        let _ = tool_mode();
+        tool_active.set(false);
+        current_path.set(vec![]);
+        preview_canvas.clear_canvas();
+    });
+
+    // a selection only makes sense on the layer it was made on
+    use_effect(move || {
+        let _ = (app_state.active_layer)();
+        let _ = tool_mode();
+        selection.set(vec![]);
+    });
+
+    use_effect(move || {
+        let _ = (app_state.active_layer)();
+        pixel_selection.set(vec![]);
+    });
+
+    // the marching ants, kept out of the way while a tool is drawing on the preview
+    use_effect(move || {
+        let phase = ants_phase();
+        let outline = pixel_selection();
+        let selecting = matches!(*tool_mode.peek(), ToolMode::Marquee | ToolMode::Lasso);
+        if selecting && !*tool_active.peek() {
+            preview_canvas.clear_canvas();
+            if !outline.is_empty() {
+                preview_canvas.draw_marching_ants(&outline, phase);
+            }
+        }
+    });
+
+    // the selection box follows the selection, whatever undo does to it and
+    // the canvas being redrawn at another scale
+    let show_selection = move || {
+        preview_canvas.clear_canvas();
+        let selected = selected_commands(
+            app_state.history.peek().undo_commands(),
+            *app_state.active_layer.peek(),
+            &selection.peek(),
+        );
+        if let Some(bounds) = union_bounds(selected.iter().map(|(_, command)| command)) {
+            preview_canvas.draw_selection(bounds, viewport.peek().zoom);
+        }
+    };
+    use_effect(move || {
+        let _ = selection();
+        let _ = (app_state.history)();
+        let _ = canvas_signal();
+        // the handles keep their size on screen
+        let _ = viewport();
+        if *tool_mode.peek() == ToolMode::Select {
+            show_selection();
+        }
+    });
+
+    // a new, resized or rescaled canvas starts out blank, so replay the
+    // history onto it, without the checkpoints taken at the old size
+    use_effect(move || {
+        let _ = canvas_signal();
+        app_state.history.peek().forget_checkpoints();
+        app_state.redraw_all_layers();
+    });
+
+    // the shape spanned by the current drag, in the current colors
+    let dragged_shape = move || ShapeProperties {
+        start_point: point_down(),
+        end_point: current_point(),
+        color: rgb_color(),
+        line_width: line_width(),
+        fill_color: fill_color(),
+        fill_mode: fill_mode(),
+    };
+
+    // deletes the topmost command under the object eraser, a brush as wide as
+    // the line width on screen
+    let delete_touched = move || {
+        let layer = *app_state.active_layer.peek();
+        let touched = command_at(
+            app_state.history.peek().undo_commands(),
+            layer,
+            current_point(),
+            line_width() / 2.0 / viewport.peek().zoom,
+        );
+        if let Some(index) = touched {
+            app_state.record(Command::Delete(index));
+            app_state.redraw_layer(layer);
+        }
+    };
+
+    // the selected commands as the current drag would leave them
+    let dragged_selection = move || {
+        let selected = selected_commands(
+            app_state.history.peek().undo_commands(),
+            *app_state.active_layer.peek(),
+            &selection.peek(),
+        );
+        let transformation = selection_drag().transformation(
+            selected.iter().map(|(index, _)| *index).collect(),
+            point_down(),
+            current_point(),
+        );
+        (selected, transformation)
+    };
+
+    // the whole pixels the marquee or lasso selection has been dragged by
+    let drag_offset = move || {
+        let offset = (current_point() - point_down()).round();
+        (offset.x as i32, offset.y as i32)
+    };
+    let moved_outline = move |dx: i32, dy: i32| -> Vec<ElementPoint> {
+        let offset = euclid::vec2(dx as f64, dy as f64);
+        pixel_selection
+            .peek()
+            .iter()
+            .map(|point| *point + offset)
+            .collect()
+    };
+    // the outline of a new marquee or lasso selection, the marquee snapped to whole pixels
+    let dragged_outline = move || match pressed_tool() {
+        ToolMode::Lasso => current_path(),
+        _ => {
+            let (start, end) = (point_down(), current_point());
+            corner_handles((start.min(end).round(), start.max(end).round())).to_vec()
+        }
+    };
+
+    // MARQUEE AND LASSO
+    // dragging inside the selection moves its pixels, anywhere else starts a new selection
+    let pixel_select_down = move || {
+        point_down.set(current_point());
+        let outline = pixel_selection();
+        if !outline.is_empty() && winding_number(&outline, current_point()) != 0 {
+            floating.set(drawing_canvas.copy_pixels(&outline));
+            drawing_canvas.draw_command(&Command::Patch(RasterPatch {
+                cleared: outline,
+                pasted: None,
+            }));
+        } else {
+            floating.set(None);
+            pixel_selection.set(vec![]);
+            current_path.set(vec![current_point()]);
+        }
+    };
+    let pixel_select_move = move || {
+        preview_canvas.clear_canvas();
+        if let Some(patch) = floating() {
+            let (dx, dy) = drag_offset();
+            preview_canvas.draw_pixels(&patch.moved(dx, dy));
+            preview_canvas.draw_marching_ants(&moved_outline(dx, dy), *ants_phase.peek());
+        } else {
+            if pressed_tool() == ToolMode::Lasso {
+                current_path.push(current_point());
+            }
+            preview_canvas.draw_marching_ants(&dragged_outline(), *ants_phase.peek());
+        }
+    };
+    let pixel_select_up = move || {
+        if let Some(patch) = floating() {
+            floating.set(None);
+            let (dx, dy) = drag_offset();
+            if (dx, dy) == (0, 0) {
+                // put the lifted pixels back
+                app_state.redraw_layer(*app_state.active_layer.peek());
+                return;
+            }
+            app_state.apply_patch(RasterPatch {
+                cleared: pixel_selection(),
+                pasted: Some(patch.moved(dx, dy)),
+            });
+            pixel_selection.set(moved_outline(dx, dy));
+        } else {
+            let outline = dragged_outline();
+            current_path.set(vec![]);
+            // a click without a drag just deselects
+            let encloses = points_bounds(&outline)
+                .is_some_and(|(min, max)| max.x - min.x >= 1.0 && max.y - min.y >= 1.0);
+            pixel_selection.set(if encloses { outline } else { vec![] });
+        }
+    };
+
     // HANDLERS
     let tool_handlers = enum_map![
         ToolMode::Pen => CanvasToolHandler {
             // PEN
+            // the stroke so far is redrawn whole on the preview, so a translucent
+            // stroke stays even where it crosses itself
             onmousedown: Arc::new(Mutex::new(move || {
                 current_path.push(current_point());
-                drawing_canvas.begin_path();
-                drawing_canvas.draw_point(&current_point(), rgb_color(), line_width());
             })),
             onmousemove: Arc::new(Mutex::new(move || {
                 current_path.push(current_point());
-                drawing_canvas.draw_point(&current_point(), rgb_color(), line_width());
+                preview_canvas.clear_canvas();
+                preview_canvas.draw_polyline(&current_path(), &rgb_color(), line_width());
             })),
             onmouseup: Arc::new(Mutex::new(move || {
-                undo_commands.push(Command::DrawPath(Path {
+                preview_canvas.clear_canvas();
+                let command = Command::DrawPath(Path {
                     points: current_path(),
                     color: rgb_color(),
                     line_width: line_width(),
-                }));
+                });
+                drawing_canvas.draw_command(&command);
+                app_state.record(command);
                 current_path.set(vec![]);
             })),
         },
@@ -63,50 +299,117 @@ pub fn DrawCanvas() -> Element {
             //ERASER
             onmousedown: Arc::new(Mutex::new(move || {
                 current_path.push(current_point());
-                drawing_canvas.begin_path();
-                drawing_canvas.erase(current_point(), line_width());
+                drawing_canvas.erase(&[current_point()], line_width());
             })),
             onmousemove: Arc::new(Mutex::new(move || {
+                // erase from the previous sample, however far the mouse jumped
+                let previous = current_path().last().copied().unwrap_or(current_point());
                 current_path.push(current_point());
-                drawing_canvas.erase(current_point(), line_width());
+                drawing_canvas.erase(&[previous, current_point()], line_width());
             })),
             onmouseup: Arc::new(Mutex::new(move || {
-                undo_commands.push(Command::ErasePath(Path {
+                app_state.record(Command::ErasePath(Path {
                     points: current_path(),
-                    color: Color(0.0, 0.0, 0.0),
+                    color: Color(0.0, 0.0, 0.0, 1.0),
                     line_width: line_width(),
                 }));
                 current_path.set(vec![]);
             })),
         },
 
-        ToolMode::Line => CanvasToolHandler {
+        ToolMode::ObjectEraser => CanvasToolHandler {
+            // deletes whatever it is dragged over, which undoes all at once
+            onmousedown: Arc::new(Mutex::new(delete_touched)),
+            onmousemove: Arc::new(Mutex::new(delete_touched)),
+            onmouseup: Arc::new(Mutex::new(move || {})),
+        },
+
+        ToolMode::Select => CanvasToolHandler {
+            // grabbing a handle or the selection transforms it, clicking a
+            // command selects it, and dragging over empty space selects by area
             onmousedown: Arc::new(Mutex::new(move || {
                 point_down.set(current_point());
+                let layer = *app_state.active_layer.peek();
+                let selected = selected_commands(app_state.history.peek().undo_commands(), layer, &selection());
+                let zoom = viewport.peek().zoom;
+                let grabbed = union_bounds(selected.iter().map(|(_, command)| command))
+                    .and_then(|bounds| SelectionDrag::grab(bounds, current_point(), zoom));
+                let radius = SELECT_DISTANCE / zoom;
+                let drag = match grabbed {
+                    Some(drag) => drag,
+                    None => match command_at(app_state.history.peek().undo_commands(), layer, current_point(), radius) {
+                        Some(index) => {
+                            selection.set(vec![index]);
+                            SelectionDrag::Move
+                        }
+                        None => {
+                            selection.set(vec![]);
+                            SelectionDrag::Band
+                        }
+                    },
+                };
+                selection_drag.set(drag);
             })),
             onmousemove: Arc::new(Mutex::new(move || {
                 preview_canvas.clear_canvas();
-                preview_canvas.draw_line(&point_down(), &current_point(), &rgb_color(), line_width());
+                if selection_drag() == SelectionDrag::Band {
+                    preview_canvas.draw_box((point_down(), current_point()));
+                    return;
+                }
+                let (selected, transformation) = dragged_selection();
+                let moved: Vec<Command> = selected
+                    .iter()
+                    .map(|(_, command)| transform_command(command, &transformation))
+                    .collect();
+                moved.iter().for_each(|command| preview_canvas.draw_command(command));
+                if let Some(bounds) = union_bounds(&moved) {
+                    preview_canvas.draw_box(bounds);
+                }
             })),
             onmouseup: Arc::new(Mutex::new(move || {
-                    preview_canvas.clear_canvas();
+                if selection_drag() == SelectionDrag::Band {
+                    let layer = *app_state.active_layer.peek();
+                    let band = (point_down(), current_point());
+                    selection.set(commands_within(app_state.history.peek().undo_commands(), layer, band));
+                    return;
+                }
+                let (_, transformation) = dragged_selection();
+                if transformation.is_identity() {
+                    show_selection();
+                } else {
+                    app_state.record(Command::Transform(transformation));
+                    app_state.redraw_layer(*app_state.active_layer.peek());
+                }
+            })),
+        },
 
-                    shape_properties.set(ShapeProperties {
-                        start_point: point_down(),
-                        end_point: current_point(),
-                        color: rgb_color(),
-                        line_width: line_width(),
-                    });
-                    undo_commands.push(Command::DrawLine(
-                            shape_properties()
-                    ));
-                    drawing_canvas.draw_line(
-                        &point_down(),
-                        &current_point(),
-                        &rgb_color(),
-                        line_width(),
-                    );
+        ToolMode::Marquee => CanvasToolHandler {
+            onmousedown: Arc::new(Mutex::new(pixel_select_down)),
+            onmousemove: Arc::new(Mutex::new(pixel_select_move)),
+            onmouseup: Arc::new(Mutex::new(pixel_select_up)),
+        },
 
+        ToolMode::Lasso => CanvasToolHandler {
+            onmousedown: Arc::new(Mutex::new(pixel_select_down)),
+            onmousemove: Arc::new(Mutex::new(pixel_select_move)),
+            onmouseup: Arc::new(Mutex::new(pixel_select_up)),
+        },
+
+        ToolMode::Line => CanvasToolHandler {
+            onmousedown: Arc::new(Mutex::new(move || {
+                point_down.set(current_point());
+            })),
+            onmousemove: Arc::new(Mutex::new(move || {
+                preview_canvas.clear_canvas();
+                preview_canvas.draw_command(&Command::DrawLine(dragged_shape()));
+            })),
+            onmouseup: Arc::new(Mutex::new(move || {
+                preview_canvas.clear_canvas();
+
+                shape_properties.set(dragged_shape());
+                let command = Command::DrawLine(shape_properties());
+                drawing_canvas.draw_command(&command);
+                app_state.record(command);
             })),
         },
         ToolMode::Rectangle => CanvasToolHandler {
@@ -115,27 +418,15 @@ pub fn DrawCanvas() -> Element {
             })),
             onmousemove: Arc::new(Mutex::new(move || {
                 preview_canvas.clear_canvas();
-                preview_canvas.draw_rect(&point_down(), &current_point(), &rgb_color(), line_width());
+                preview_canvas.draw_command(&Command::DrawRectangle(dragged_shape()));
             })),
             onmouseup: Arc::new(Mutex::new(move || {
-                    preview_canvas.clear_canvas();
-
-                    shape_properties.set(ShapeProperties {
-                        start_point: point_down(),
-                        end_point: current_point(),
-                        color: rgb_color(),
-                        line_width: line_width(),
-                    });
-                    undo_commands.push(Command::DrawRectangle(
-                            shape_properties()
-                    ));
-                    drawing_canvas.draw_rect(
-                        &point_down(),
-                        &current_point(),
-                        &rgb_color(),
-                        line_width(),
-                    );
+                preview_canvas.clear_canvas();
 
+                shape_properties.set(dragged_shape());
+                let command = Command::DrawRectangle(shape_properties());
+                drawing_canvas.draw_command(&command);
+                app_state.record(command);
             })),
         },
         ToolMode::Circle => CanvasToolHandler {
@@ -144,103 +435,258 @@ pub fn DrawCanvas() -> Element {
             })),
             onmousemove: Arc::new(Mutex::new(move || {
                 preview_canvas.clear_canvas();
-                preview_canvas.draw_circle(&point_down(), &current_point(), &rgb_color(), line_width());
+                preview_canvas.draw_command(&Command::DrawCircle(dragged_shape()));
             })),
             onmouseup: Arc::new(Mutex::new(move || {
-                    preview_canvas.clear_canvas();
+                preview_canvas.clear_canvas();
+
+                shape_properties.set(dragged_shape());
+                let command = Command::DrawCircle(shape_properties());
+                drawing_canvas.draw_command(&command);
+                app_state.record(command);
+            })),
+        },
 
-                    shape_properties.set(ShapeProperties {
-                        start_point: point_down(),
-                        end_point: current_point(),
+        ToolMode::Polygon => CanvasToolHandler {
+            onmousedown: Arc::new(Mutex::new(move || {
+                let vertices = current_path();
+                let close_distance = CLOSE_DISTANCE / viewport.peek().zoom;
+                let near = |vertex: Option<&ElementPoint>| {
+                    vertex.is_some_and(|vertex| (current_point() - *vertex).length() <= close_distance)
+                };
+
+                // a click on the first vertex, or the second click of a double-click, closes it
+                if vertices.len() > 2 && (near(vertices.first()) || near(vertices.last())) {
+                    preview_canvas.clear_canvas();
+                    let command = Command::DrawPolygon(Polygon {
+                        points: vertices,
                         color: rgb_color(),
                         line_width: line_width(),
+                        fill_color: fill_color(),
+                        fill_mode: fill_mode(),
                     });
-                    undo_commands.push(Command::DrawCircle(
-                            shape_properties()
-                    ));
-                    drawing_canvas.draw_circle(
-                        &point_down(),
-                        &current_point(),
-                        &rgb_color(),
-                        line_width(),
-                    );
-
+                    drawing_canvas.draw_command(&command);
+                    app_state.record(command);
+                    current_path.set(vec![]);
+                } else if !near(vertices.last()) {
+                    current_path.push(current_point());
+                }
+            })),
+            onmousemove: Arc::new(Mutex::new(move || {
+                let mut rubber_band = current_path();
+                rubber_band.push(current_point());
+                preview_canvas.clear_canvas();
+                preview_canvas.draw_polyline(&rubber_band, &rgb_color(), line_width());
+            })),
+            onmouseup: Arc::new(Mutex::new(move || {
+                // keep receiving mouse moves between clicks until the polygon is closed
+                if !current_path().is_empty() {
+                    tool_active.set(true);
+                }
             })),
         },
 
-        _ => CanvasToolHandler {
-            onmousedown: Arc::new(Mutex::new(move || {})),
+        ToolMode::Fill => CanvasToolHandler {
+            onmousedown: Arc::new(Mutex::new(move || {
+                let command = Command::FloodFill(FloodFill {
+                    point: current_point(),
+                    color: rgb_color(),
+                    tolerance: bucket_tolerance(),
+                    connectivity: bucket_connectivity(),
+                });
+                drawing_canvas.draw_command(&command);
+                app_state.record(command);
+            })),
             onmousemove: Arc::new(Mutex::new(move || {})),
             onmouseup: Arc::new(Mutex::new(move || {})),
-        }
-    ];
+        },
 
-    let CanvasToolHandler {
-        onmousedown,
-        onmousemove,
-        onmouseup,
-    } = tool_handlers[tool_mode()].clone();
+        ToolMode::Eyedropper => CanvasToolHandler {
+            // picks up the color as it shows on screen, keeping it while dragging
+            onmousedown: Arc::new(Mutex::new(move || {
+                let color = canvas_signal().sample_color(
+                    &layers.peek(),
+                    current_point(),
+                    eyedropper_size(),
+                );
+                app_state.set_color(color);
+            })),
+            onmousemove: Arc::new(Mutex::new(move || {
+                let color = canvas_signal().sample_color(
+                    &layers.peek(),
+                    current_point(),
+                    eyedropper_size(),
+                );
+                app_state.set_color(color);
+            })),
+            onmouseup: Arc::new(Mutex::new(move || {})),
+        },
+    ];
 
+    let down_handlers = tool_handlers.clone();
     let mouse_down_handler = move |event: Event<MouseData>| {
+        let screen_point = event.element_coordinates();
+        // the middle button, or any button with Space held, drags the view around
+        if space_held() || event.trigger_button() == Some(MouseButton::Auxiliary) {
+            // and doesn't start the browser's autoscroll
+            event.prevent_default();
+            pan_from.set(Some(screen_point));
+            return;
+        }
+        // holding Alt samples colors with the pen instead of drawing
+        let tool = match (tool_mode(), event.modifiers().alt()) {
+            (ToolMode::Pen, true) => ToolMode::Eyedropper,
+            (tool, _) => tool,
+        };
+        if tool.draws() && !app_state.active_layer_editable() {
+            return;
+        }
+        pressed_tool.set(tool);
         tool_active.set(true);
+        // the whole press undoes at once
+        app_state.begin_transaction();
 
-        current_point.set(event.element_coordinates());
-        redo_commands.set(vec![]);
-
-        let mut handle = onmousedown.lock().unwrap();
+        current_point.set(viewport().to_document(screen_point));
+        let mut handle = down_handlers[tool].onmousedown.lock().unwrap();
         handle();
     };
 
+    let move_handlers = tool_handlers.clone();
     let mouse_move_handler = move |event: Event<MouseData>| {
+        let screen_point = event.element_coordinates();
+        if let Some(from) = pan_from() {
+            app_state.set_viewport(viewport().panned(screen_point - from));
+            pan_from.set(Some(screen_point));
+            return;
+        }
         if tool_active() {
-            current_point.set(event.element_coordinates());
+            current_point.set(viewport().to_document(screen_point));
 
-            let mut handle = onmousemove.lock().unwrap();
+            let mut handle = move_handlers[pressed_tool()].onmousemove.lock().unwrap();
             handle();
         };
     };
 
     let mouse_up_handler = move || {
+        if pan_from().is_some() {
+            pan_from.set(None);
+            return;
+        }
         if tool_active() {
             tool_active.set(false);
 
-            let mut handle = onmouseup.lock().unwrap();
+            let mut handle = tool_handlers[pressed_tool()].onmouseup.lock().unwrap();
             handle();
+            app_state.end_transaction();
         }
     };
 
-    rsx! {
+    // previews look the way they will once they land on the active layer
+    let preview_opacity = layers()
+        .iter()
+        .find(|layer| layer.id == (app_state.active_layer)())
+        .map_or(1.0, |layer| layer.opacity);
+
+    // the wheel zooms about the pointer, and so does pinching a trackpad,
+    // which browsers send as the wheel with Ctrl held
+    let wheel_handler = move |event: Event<WheelData>| {
+        event.prevent_default();
+        let per_pixel = match event.modifiers().ctrl() {
+            true => PINCH_ZOOM,
+            false => WHEEL_ZOOM,
+        };
+        let zoom = viewport().zoom * (-scrolled_pixels(event.delta()) * per_pixel).exp();
+        app_state.set_viewport(viewport().zoomed(zoom, event.element_coordinates()));
+    };
+
+    let cursor = if pan_from().is_some() {
+        "grabbing".to_string()
+    } else if space_held() {
+        "grab".to_string()
+    } else if !tool_mode().draws() || app_state.active_layer_editable() {
+        canvas_cursor()
+    } else {
+        "not-allowed".to_string()
+    };
 
+    // the canvases are drawn `scale` times the drawing's size, and CSS takes
+    // them the rest of the way to the zoom
+    let (canvas_width, canvas_height) = drawing_canvas.canvas_size();
+    let pan = viewport().pan;
+    let css_scale = viewport().zoom / drawing_canvas.scale;
+    // past the sharpest the canvases get, show their pixels rather than blur them
+    let image_rendering = if css_scale > 1.0 { "pixelated" } else { "auto" };
+
+    rsx! {
         div {
-            canvas { // DRAWING CANVAS
-
-                id: drawing_canvas.properties.name,
-                width: drawing_canvas.properties.width,
-                height: drawing_canvas.properties.height,
-                // border: "2px solid black",
-                z_index: 0,
-                position: "absolute",
-                cursor: canvas_cursor(),
-                background: "white",
-                class: "drawing-canvas",
+            class: "canvas-area",
+            div {
+                id: VIEWPORT_ID,
+                class: "canvas-viewport",
+                cursor: cursor,
 
                 onmousedown: move |event| mouse_down_handler.clone()(event),
                 onmousemove: move |event| mouse_move_handler.clone()(event),
 
                 // onmouseleave: move |_event| mouse_up_handler.clone()(), //leave and up have the same
                 onmouseup: move |_event| mouse_up_handler.clone()(),
+                onwheel: wheel_handler,
+
+                div {
+                    class: "canvas-stack",
+                    transform: "translate({pan.x}px, {pan.y}px) scale({css_scale})",
+                    for layer in layers() {
+                        canvas { // LAYER CANVAS
+                            key: "{layer.id}",
+                            id: canvas_signal().for_layer(layer.id).element_id(),
+                            width: canvas_width,
+                            height: canvas_height,
+                            class: "drawing-canvas layer-canvas",
+                            opacity: layer.opacity,
+                            visibility: if layer.visible { "visible" } else { "hidden" },
+                            image_rendering: image_rendering,
+                        }
+                    }
+
+                    canvas { // PREVIEW CANVAS, on top of every layer
+                        id: preview_canvas.properties.name,
+                        width: canvas_width,
+                        height: canvas_height,
+                        position: "relative",
+                        opacity: preview_opacity,
+                        class: "drawing-canvas",
+                        image_rendering: image_rendering,
+                    }
+                }
             }
 
-            canvas {
-                id: preview_canvas.properties.name,
-                width: preview_canvas.properties.width,
-                height: preview_canvas.properties.height,
-                // border: "2px solid black",
-                z_index: 1,
-                position: "relative",
-                pointer_events: "none",
-                class: "drawing-canvas",
+            canvas { // SCRATCH CANVAS, never shown
+                id: scratch_canvas.element_id(),
+                width: scratch_canvas.properties.width,
+                height: scratch_canvas.properties.height,
+                display: "none",
             }
+
+            canvas { // THUMBNAIL CANVAS, never shown
+                id: thumbnail_canvas.element_id(),
+                width: canvas_width,
+                height: canvas_height,
+                display: "none",
+            }
+
+            ZoomBar {}
         }
     }
 }
+
+// PRIVATE
+
+/// How far a wheel event scrolled, in pixels.
+fn scrolled_pixels(delta: WheelDelta) -> f64 {
+    match delta {
+        WheelDelta::Pixels(delta) => delta.y,
+        // roughly a line of text, and the height of a screen
+        WheelDelta::Lines(delta) => delta.y * 20.0,
+        WheelDelta::Pages(delta) => delta.y * 800.0,
+    }
+}
@@ -0,0 +1,131 @@
+use crate::app_state::AppState;
+use crate::drawing::store_setting;
+use crate::history::{HistoryBudget, NodeId, NodeState, BUDGET_STORAGE_KEY};
+use dioxus::prelude::*;
+use dioxus_free_icons::icons::ld_icons::*;
+use dioxus_free_icons::Icon;
+
+/// Branches deeper than this are indented no further, to fit the sidebar.
+const MAX_INDENT: usize = 6;
+const MEGABYTE: usize = 1024 * 1024;
+/// Thumbnails are drawn this many pixels across, twice what they're shown
+/// at, so they stay sharp on high density screens.
+const THUMBNAIL_WIDTH: f64 = 96.0;
+
+/// Every state the drawing has been in, branches included. Clicking one
+/// goes back to it.
+#[allow(non_snake_case)]
+pub fn HistoryPanel() -> Element {
+    // SIGNALS
+    let mut app_state = use_context::<AppState>();
+    let history = use_context::<AppState>().history;
+    let layers = use_context::<AppState>().layers;
+    let mut history_budget = use_context::<AppState>().history_budget;
+    // thumbnails are drawn the first time they're shown, so only while the list is open
+    let mut expanded = use_signal(|| false);
+
+    // HANDLERS
+    let thumbnail = move |node: Option<NodeId>| {
+        let history = history.peek();
+        let layers = layers.peek();
+        history.thumbnail(node, &layers, || {
+            app_state
+                .thumbnail_canvas()
+                .thumbnail(&layers, THUMBNAIL_WIDTH, |canvas, layer| {
+                    history.render_layer_at(canvas, layer, node)
+                })
+        })
+    };
+
+    let mut set_budget = move |budget: HistoryBudget| {
+        store_setting(BUDGET_STORAGE_KEY, &serde_json::to_string(&budget).unwrap());
+        history_budget.set(budget);
+        app_state.compact_history();
+    };
+
+    let layer_name = move |id| {
+        layers()
+            .iter()
+            .find(|layer| layer.id == id)
+            .map(|layer| layer.name.clone())
+            .unwrap_or_default()
+    };
+
+    rsx! {
+        div { // HISTORY
+            class: "layers-div",
+            div {
+                class: "layers-header",
+                "History"
+                button {
+                    title: if expanded() { "Hide history" } else { "Show history" },
+                    onclick: move |_event| expanded.set(!expanded()),
+                    if expanded() {
+                        Icon { icon: LdChevronUp }
+                    } else {
+                        Icon { icon: LdChevronDown }
+                    }
+                }
+            }
+
+            if expanded() {
+                div { // BUDGET, past which the oldest history is flattened
+                    class: "history-budget",
+                    title: "Older history is flattened into an image, which can't be undone",
+                    "Keep "
+                    input {
+                        type: "number",
+                        min: 10,
+                        value: history_budget().max_entries,
+                        onchange: move |event| {
+                            if let Ok(max_entries @ 10..) = event.value().parse::<usize>() {
+                                set_budget(HistoryBudget { max_entries, ..history_budget() });
+                            }
+                        },
+                    }
+                    " steps and "
+                    input {
+                        type: "number",
+                        min: 1,
+                        value: history_budget().max_bytes / MEGABYTE,
+                        onchange: move |event| {
+                            if let Ok(megabytes @ 1..) = event.value().parse::<usize>() {
+                                set_budget(HistoryBudget { max_bytes: megabytes * MEGABYTE, ..history_budget() });
+                            }
+                        },
+                    }
+                    " MB"
+                }
+                div {
+                    class: "history-list",
+                    div {
+                        class: if history.read().current().is_none() { "history-row active-layer" } else { "history-row" },
+                        onclick: move |_event| app_state.jump_to(None),
+                        img { class: "history-thumbnail", src: thumbnail(None) }
+                        "Blank canvas"
+                    }
+                    for (node, level) in history.read().nodes() {
+                        div {
+                            key: "{node}",
+                            class: match history.read().node_state(node) {
+                                NodeState::Current => "history-row active-layer",
+                                NodeState::Done => "history-row",
+                                NodeState::Undone => "history-row history-undone",
+                                NodeState::Abandoned => "history-row history-abandoned",
+                            },
+                            // each branch is indented under the state it branched off
+                            padding_left: "{level.min(MAX_INDENT) * 12 + 2}px",
+                            onclick: move |_event| app_state.jump_to(Some(node)),
+                            img { class: "history-thumbnail", src: thumbnail(Some(node)) }
+                            span { {history.read().entry(node).command.label()} }
+                            span {
+                                class: "history-layer",
+                                {layer_name(history.read().entry(node).layer)}
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::app_state::AppState;
+use crate::drawing::store_setting;
+use crate::keymap::{Action, KeyCombo, Keymap, STORAGE_KEY};
+use dioxus::prelude::*;
+use dioxus_free_icons::icons::ld_icons::*;
+use dioxus_free_icons::Icon;
+
+/// Lists the keyboard shortcuts and rebinds them: click an action's keys,
+/// then press the new ones. Changes are saved right away.
+#[allow(non_snake_case)]
+pub fn KeymapPanel() -> Element {
+    // SIGNALS
+    let mut keymap = use_context::<AppState>().keymap;
+    // the action waiting for its new keys to be pressed
+    let mut capturing = use_signal(|| None::<Action>);
+    let mut error = use_signal(|| None::<String>);
+
+    // HANDLERS
+    let mut save = move |changed: Keymap| {
+        store_setting(STORAGE_KEY, &changed.to_json());
+        keymap.set(changed);
+    };
+
+    let mut capture_handler = move |action: Action, event: Event<KeyboardData>| {
+        if capturing() != Some(action) {
+            return;
+        }
+        // the keys are meant for the binding, not to run whatever they're bound to now
+        event.stop_propagation();
+        event.prevent_default();
+
+        let key = event.key().to_string();
+        if KeyCombo::is_modifier(&key) {
+            return;
+        }
+        capturing.set(None);
+        if key == "Escape" {
+            return;
+        }
+        let modifiers = event.modifiers();
+        let keys = KeyCombo::new(
+            &key,
+            modifiers.ctrl() || modifiers.meta(),
+            modifiers.shift(),
+            modifiers.alt(),
+        );
+        let mut changed = keymap();
+        match changed.rebind(action, keys) {
+            Ok(()) => save(changed),
+            Err(message) => error.set(Some(message)),
+        }
+    };
+
+    rsx! {
+        details {
+            class: "keymap-div",
+            summary { "Keyboard shortcuts" }
+            for (keys, actions) in keymap().conflicts() {
+                div {
+                    class: "color-field-error",
+                    "{keys} is bound to more than one action: "
+                    {actions.iter().map(|action| action.label()).collect::<Vec<_>>().join(", ")}
+                }
+            }
+            if let Some(message) = error() {
+                div {
+                    class: "color-field-error",
+                    "{message}"
+                }
+            }
+            for action in Action::ALL {
+                div {
+                    class: "keymap-row",
+                    span { {action.label()} }
+                    button {
+                        class: "keymap-keys",
+                        title: "Click, then press the new shortcut (Escape cancels)",
+                        onclick: move |_event| {
+                            error.set(None);
+                            capturing.set(Some(action));
+                        },
+                        onkeydown: move |event| capture_handler(action, event),
+                        onblur: move |_event| {
+                            if capturing() == Some(action) {
+                                capturing.set(None);
+                            }
+                        },
+                        if capturing() == Some(action) {
+                            "Press keys…"
+                        } else {
+                            match keymap().keys(action).as_slice() {
+                                [] => "None".to_string(),
+                                keys => keys.iter().map(|keys| keys.to_string()).collect::<Vec<_>>().join(", "),
+                            }
+                        }
+                    }
+                    button {
+                        title: "Remove the shortcut",
+                        onclick: move |_event| {
+                            let mut changed = keymap();
+                            changed.unbind(action);
+                            save(changed);
+                        },
+                        Icon { icon: LdX }
+                    }
+                }
+            }
+            button {
+                onclick: move |_event| {
+                    error.set(None);
+                    save(Keymap::default());
+                },
+                "Reset to defaults"
+            }
+        }
+    }
+}
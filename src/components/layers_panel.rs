@@ -0,0 +1,134 @@
+use crate::app_state::AppState;
+use crate::drawing::*;
+use crate::layers::{new_layer, Layer, LayerId};
+use dioxus::prelude::*;
+use dioxus_free_icons::icons::ld_icons::*;
+use dioxus_free_icons::Icon;
+
+#[allow(non_snake_case)]
+pub fn LayersPanel() -> Element {
+    // SIGNALS
+    let mut layers = use_context::<AppState>().layers;
+    let mut active_layer = use_context::<AppState>().active_layer;
+    let mut history = use_context::<AppState>().history;
+
+    // HANDLERS
+    let mut add_layer_handler = move || {
+        let layer = new_layer(&layers());
+        active_layer.set(layer.id);
+        layers.push(layer);
+    };
+
+    let mut delete_layer_handler = move |id: LayerId| {
+        if layers.len() == 1 {
+            show_message("A drawing needs at least one layer.");
+            return;
+        }
+        let has_history = history.read().touches_layer(id);
+        if has_history && !ask_confirmation("Delete this layer and everything drawn on it?") {
+            return;
+        }
+
+        // the layer's history goes with it, so undo can't bring back strokes without a layer
+        history.write().remove_layer(id);
+        let index = layers().iter().position(|layer| layer.id == id).unwrap();
+        layers.remove(index);
+        if active_layer() == id {
+            active_layer.set(layers()[index.saturating_sub(1)].id);
+        }
+    };
+
+    // positive moves up the stack
+    let mut move_layer_handler = move |id: LayerId, offset: isize| {
+        let index = layers().iter().position(|layer| layer.id == id).unwrap();
+        let target = index as isize + offset;
+        if (0..layers.len() as isize).contains(&target) {
+            layers.write().swap(index, target as usize);
+        }
+    };
+
+    rsx! {
+        div { // LAYERS
+            class: "layers-div",
+            div {
+                class: "layers-header",
+                "Layers"
+                button {
+                    title: "New layer",
+                    onclick: move |_event| add_layer_handler(),
+                    Icon {
+                        icon: LdPlus,
+                    }
+                }
+            }
+
+            // listed top to bottom, the way they stack on screen
+            for layer in layers().into_iter().rev() {
+                div {
+                    key: "{layer.id}",
+                    class: if layer.id == active_layer() { "layer-row active-layer" } else { "layer-row" },
+                    onclick: move |_event| active_layer.set(layer.id),
+
+                    button { // VISIBILITY
+                        title: "Show or hide",
+                        onclick: move |_event| update_layer(layers, layer.id, |layer| layer.visible = !layer.visible),
+                        if layer.visible {
+                            Icon { icon: LdEye }
+                        } else {
+                            Icon { icon: LdEyeOff }
+                        }
+                    }
+                    button { // LOCK
+                        title: "Lock or unlock",
+                        onclick: move |_event| update_layer(layers, layer.id, |layer| layer.locked = !layer.locked),
+                        if layer.locked {
+                            Icon { icon: LdLock }
+                        } else {
+                            Icon { icon: LdLockOpen }
+                        }
+                    }
+                    input { // NAME
+                        class: "layer-name",
+                        value: layer.name.clone(),
+                        oninput: move |event| update_layer(layers, layer.id, |layer| layer.name = event.value()),
+                    }
+                    button { // UP
+                        title: "Move up",
+                        onclick: move |_event| move_layer_handler(layer.id, 1),
+                        Icon { icon: LdChevronUp }
+                    }
+                    button { // DOWN
+                        title: "Move down",
+                        onclick: move |_event| move_layer_handler(layer.id, -1),
+                        Icon { icon: LdChevronDown }
+                    }
+                    button { // DELETE
+                        title: "Delete layer",
+                        onclick: move |_event| delete_layer_handler(layer.id),
+                        Icon { icon: LdTrash }
+                    }
+                    input { // OPACITY
+                        class: "layer-opacity",
+                        title: "Opacity: {(layer.opacity * 100.0).round()}%",
+                        type: "range",
+                        min: 0,
+                        max: 100,
+                        value: layer.opacity * 100.0,
+                        oninput: move |event| {
+                            let opacity = event.value().parse::<f64>().unwrap() / 100.0;
+                            update_layer(layers, layer.id, |layer| layer.opacity = opacity);
+                        },
+                    }
+                }
+            }
+        }
+    }
+}
+
+// PRIVATE
+
+fn update_layer(mut layers: Signal<Vec<Layer>>, id: LayerId, change: impl FnOnce(&mut Layer)) {
+    if let Some(layer) = layers.write().iter_mut().find(|layer| layer.id == id) {
+        change(layer);
+    }
+}
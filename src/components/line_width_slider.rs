@@ -1,3 +1,4 @@
+use crate::drawing::LINE_WIDTHS;
 use crate::AppState;
 use dioxus::prelude::*;
 
@@ -14,8 +15,8 @@ pub fn LineWidthSlider() -> Element {
             }
             input {
                 type: "range",
-                min: 1.0,
-                max: 10.0,
+                min: *LINE_WIDTHS.start(),
+                max: *LINE_WIDTHS.end(),
                 value: line_width(),
                 oninput: move |event| {
                     line_width.set(event.value().parse::<f64>().unwrap());
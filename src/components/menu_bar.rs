@@ -1,5 +1,8 @@
 use crate::app_state::AppState;
 use crate::drawing::*;
+use crate::file_format::Document;
+use crate::history::History;
+use crate::svg::{export_svg, import_svg};
 use dioxus::prelude::*;
 use dioxus_free_icons::icons::ld_icons::*;
 use dioxus_free_icons::Icon;
@@ -7,68 +10,145 @@ use dioxus_free_icons::Icon;
 #[allow(non_snake_case)]
 pub fn MenuBar() -> Element {
     // PROPERTIES
-    let drawing_canvas = use_context::<AppState>().drawing_canvas;
-    let drawing_canvas = drawing_canvas();
+    let mut app_state = use_context::<AppState>();
+    let mut canvas_signal = use_context::<AppState>().drawing_canvas;
+    let drawing_canvas = canvas_signal();
 
     // SIGNALS
-    let mut undo_commands = use_context::<AppState>().undo_commands;
-    let mut redo_commands = use_context::<AppState>().redo_commands;
+    let mut history = use_context::<AppState>().history;
+    let mut layers = use_context::<AppState>().layers;
+    let mut active_layer = use_context::<AppState>().active_layer;
 
     // HANDLERS
-    let mut clear_canvas_handler = move || {
-        drawing_canvas.clear_canvas();
-        if undo_commands().last().unwrap() != &Command::EraseCanvas {
-            undo_commands.push(Command::EraseCanvas)
-        };
-        redo_commands.set(vec![])
+    let export_svg_handler = move || {
+        let svg = export_svg(
+            &layers(),
+            history.read().undo_commands(),
+            drawing_canvas.properties.width,
+            drawing_canvas.properties.height,
+        );
+        save_text_file(&svg, "image/svg+xml", "image.svg");
     };
 
-    let mut undo_handler = move || {
-        if undo_commands.is_empty() {
-        } else {
-            drawing_canvas.clear_canvas();
-            redo_commands.push(undo_commands.pop().unwrap());
-        }
+    let open_document_handler = move |event: Event<FormData>| async move {
+        let Some((file_name, json)) = read_chosen_file(&event).await else {
+            return;
+        };
 
-        drawing_canvas.execute_commands(undo_commands());
+        match Document::from_json(&json) {
+            Ok(document) => {
+                if let Some(top) = document.layers.last() {
+                    active_layer.set(top.id);
+                }
+                layers.set(document.layers);
+                history.set(History::from_saved(document.history));
+                // setting the canvas redraws it from the new history
+                canvas_signal.set(DrawingCanvas {
+                    properties: CanvasProperties {
+                        name: drawing_canvas.properties.name,
+                        ..document.canvas
+                    },
+                    layer: None,
+                    scale: 1.0,
+                });
+                app_state.zoom_to_fit();
+            }
+            Err(error) => show_message(&format!("Could not open {}: {}", file_name, error)),
+        }
     };
 
-    let mut redo_handler = move || {
-        if redo_commands.is_empty() {
-        } else {
-            drawing_canvas.clear_canvas();
-            undo_commands.push(redo_commands.pop().unwrap())
+    let import_svg_handler = move |event: Event<FormData>| async move {
+        if !app_state.active_layer_editable() {
+            show_message("The active layer is hidden or locked.");
+            return;
         }
+        let Some((file_name, svg)) = read_chosen_file(&event).await else {
+            return;
+        };
 
-        drawing_canvas.execute_commands(undo_commands());
+        match import_svg(&svg) {
+            Ok(import) => {
+                // a file with nothing to draw leaves no empty step to undo
+                if !import.commands.is_empty() {
+                    app_state.active_canvas().execute_commands(&import.commands);
+                    // the whole file undoes in one step
+                    app_state.record(Command::group(import.commands.clone()));
+                }
+                if !import.report.is_empty() {
+                    show_message(&import.summary());
+                }
+            }
+            Err(error) => show_message(&format!("Could not import {}: {}", file_name, error)),
+        }
     };
 
     rsx! {
         div { // MENUBAR
             button { // CLEAR CANVAS
-                onclick: move |_event| clear_canvas_handler(),
+                onclick: move |_event| app_state.clear_active_layer(),
                 Icon {
                     icon: LdTrash,
                 }
             }
             button { // UNDO
-                onclick: move |_event| undo_handler(),
+                onclick: move |_event| app_state.undo(),
                 Icon {
                     icon: LdUndo,
                 }
             }
             button { // REDO
-                onclick: move |_event| redo_handler(),
+                onclick: move |_event| app_state.redo(),
                 Icon {
                     icon: LdRedo,
                 }
             }
+            label { // OPEN
+                class: "file-button",
+                title: "Open drawing",
+                Icon {
+                    icon: LdFolderOpen,
+                }
+                input {
+                    type: "file",
+                    accept: ".json",
+                    display: "none",
+                    onchange: open_document_handler,
+                }
+            }
+            label { // IMPORT SVG
+                class: "file-button",
+                title: "Import SVG",
+                Icon {
+                    icon: LdImageUp,
+                }
+                input {
+                    type: "file",
+                    accept: ".svg",
+                    display: "none",
+                    onchange: import_svg_handler,
+                }
+            }
             button { // SAVE
-                onclick: move |_event| drawing_canvas.save_canvas(),
+                title: "Save drawing",
+                onclick: move |_event| app_state.save_document(),
                 Icon {
                     icon: LdSave,
                 }
             }
+            button { // EXPORT PNG
+                title: "Export PNG",
+                onclick: move |_event| drawing_canvas.save_canvas(&layers()),
+                Icon {
+                    icon: LdImageDown,
+                }
+            }
+            button { // EXPORT SVG
+                title: "Export SVG",
+                onclick: move |_event| export_svg_handler(),
+                Icon {
+                    icon: LdFileCode,
+                }
+            }
         }
     }
 }
@@ -1,14 +1,35 @@
+mod color_fields;
+pub use color_fields::ColorFields;
+
 mod color_picker;
 pub use color_picker::ColorPicker;
 
 mod draw_canvas;
 pub use draw_canvas::DrawCanvas;
 
+mod history_panel;
+pub use history_panel::HistoryPanel;
+
+mod keymap_panel;
+pub use keymap_panel::KeymapPanel;
+
+mod layers_panel;
+pub use layers_panel::LayersPanel;
+
 mod line_width_slider;
 pub use line_width_slider::LineWidthSlider;
 
 mod menu_bar;
 pub use menu_bar::MenuBar;
 
+mod palette_panel;
+pub use palette_panel::PalettePanel;
+
 mod tool_bar;
-pub use tool_bar::ToolBar;
\ No newline at end of file
+pub use tool_bar::ToolBar;
+
+mod tool_options;
+pub use tool_options::ToolOptions;
+
+mod zoom_bar;
+pub use zoom_bar::ZoomBar;
@@ -0,0 +1,135 @@
+use crate::app_state::AppState;
+use crate::color_space::to_hex;
+use crate::drawing::*;
+use crate::palette::{recent_colors, Palette, GPL_EXTENSION, JSON_EXTENSION};
+use dioxus::prelude::*;
+use dioxus_free_icons::icons::ld_icons::*;
+use dioxus_free_icons::Icon;
+
+/// How many colors the recently used strip shows.
+const RECENT_COLORS: usize = 12;
+
+#[allow(non_snake_case)]
+pub fn PalettePanel() -> Element {
+    // SIGNALS
+    let mut app_state = use_context::<AppState>();
+    let rgb_color = use_context::<AppState>().rgb_color;
+    let mut palette = use_context::<AppState>().palette;
+    let history = use_context::<AppState>().history;
+
+    // HANDLERS
+    let import_palette_handler = move |event: Event<FormData>| async move {
+        let Some((file_name, contents)) = read_chosen_file(&event).await else {
+            return;
+        };
+        let imported = match file_name.ends_with(&format!(".{}", GPL_EXTENSION)) {
+            true => Palette::from_gpl(&contents),
+            false => Palette::from_json(&contents),
+        };
+
+        match imported {
+            Ok(imported) => {
+                if palette().swatches.is_empty() || ask_confirmation("Replace the current palette?")
+                {
+                    palette.set(imported);
+                }
+            }
+            Err(error) => show_message(&format!("Could not import {}: {}", file_name, error)),
+        }
+    };
+
+    let export_gpl_handler = move || {
+        save_text_file(
+            &palette().to_gpl(),
+            "text/plain",
+            &format!("{}.{}", palette().name, GPL_EXTENSION),
+        );
+    };
+
+    let export_json_handler = move || {
+        save_text_file(
+            &palette().to_json(),
+            "application/json",
+            &format!("{}.{}", palette().name, JSON_EXTENSION),
+        );
+    };
+
+    let recent = recent_colors(history.read().undo_commands(), RECENT_COLORS);
+
+    rsx! {
+        div { // PALETTE
+            class: "palette-div",
+            div {
+                class: "layers-header",
+                "Palette"
+                button {
+                    title: "Add the stroke color",
+                    onclick: move |_event| palette.write().add(rgb_color()),
+                    Icon { icon: LdPlus }
+                }
+                label {
+                    class: "file-button",
+                    title: "Import a .gpl or .palette.json palette",
+                    Icon { icon: LdFolderOpen }
+                    input {
+                        type: "file",
+                        accept: ".gpl,.json",
+                        display: "none",
+                        onchange: import_palette_handler,
+                    }
+                }
+                button {
+                    title: "Export as a GIMP palette",
+                    onclick: move |_event| export_gpl_handler(),
+                    Icon { icon: LdDownload }
+                }
+                button {
+                    title: "Export as JSON",
+                    onclick: move |_event| export_json_handler(),
+                    Icon { icon: LdFileJson }
+                }
+            }
+            input {
+                class: "palette-name",
+                value: palette().name,
+                oninput: move |event| palette.write().name = event.value(),
+            }
+
+            div { // SWATCHES
+                class: "swatch-grid",
+                if palette().swatches.is_empty() {
+                    span { class: "palette-hint", "Add colors with +" }
+                }
+                for (index, swatch) in palette().swatches.into_iter().enumerate() {
+                    div {
+                        key: "{index}",
+                        class: "color-swatch",
+                        title: "{swatch.name} {to_hex(swatch.color)} (right-click to remove)",
+                        background_color: swatch.color.to_css(),
+                        onclick: move |_event| app_state.set_color(swatch.color),
+                        oncontextmenu: move |event| {
+                            event.prevent_default();
+                            palette.write().swatches.remove(index);
+                        },
+                    }
+                }
+            }
+
+            div {
+                class: "palette-hint",
+                "Recently used"
+            }
+            div { // RECENT COLORS
+                class: "swatch-grid",
+                for color in recent {
+                    div {
+                        class: "color-swatch",
+                        title: to_hex(color),
+                        background_color: color.to_css(),
+                        onclick: move |_event| app_state.set_color(color),
+                    }
+                }
+            }
+        }
+    }
+}
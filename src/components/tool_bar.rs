@@ -6,44 +6,99 @@ use dioxus_free_icons::Icon;
 
 #[allow(non_snake_case)]
 pub fn ToolBar() -> Element {
-    let mut tool_mode = use_context::<AppState>().tool_mode;
-    let mut canvas_cursor = use_context::<AppState>().canvas_cursor;
+    let mut app_state = use_context::<AppState>();
+    let mut fill_mode = use_context::<AppState>().fill_mode;
 
     rsx! {
         div{
             button { // Pen
-                onclick: move |_event| {
-                    tool_mode.set(ToolMode::Pen);
-                    canvas_cursor.set("crosshair".to_string())
-                    },
+                onclick: move |_event| app_state.select_tool(ToolMode::Pen),
                 Icon {
                     icon: LdPencil,
                 }
             }
             button {
-                onclick: move |_event| tool_mode.set(ToolMode::Eraser),
+                onclick: move |_event| app_state.select_tool(ToolMode::Eraser),
                 Icon {
                     icon: LdEraser,
                 }
             }
+            button {// Select
+                title: "Select, move, scale and rotate strokes and shapes",
+                onclick: move |_event| app_state.select_tool(ToolMode::Select),
+                Icon {
+                    icon: LdMousePointer2,
+                }
+            }
+            button {// Marquee
+                title: "Select a rectangle of pixels",
+                onclick: move |_event| app_state.select_tool(ToolMode::Marquee),
+                Icon {
+                    icon: LdBoxSelect,
+                }
+            }
+            button {// Lasso
+                title: "Select pixels inside a freehand outline",
+                onclick: move |_event| app_state.select_tool(ToolMode::Lasso),
+                Icon {
+                    icon: LdLasso,
+                }
+            }
+            button {// Object eraser
+                title: "Delete whole strokes and shapes",
+                onclick: move |_event| app_state.select_tool(ToolMode::ObjectEraser),
+                Icon {
+                    icon: LdDelete,
+                }
+            }
             button {// Line
-                onclick: move |_event| tool_mode.set(ToolMode::Line),
+                onclick: move |_event| app_state.select_tool(ToolMode::Line),
                 Icon {
                     icon: LdMinus,
                 }
             }
             button {// Circle
-                onclick: move |_event| tool_mode.set(ToolMode::Circle),
+                onclick: move |_event| app_state.select_tool(ToolMode::Circle),
                 Icon {
                     icon: LdCircle,
                 }
             }
             button {// Rectangle
-                onclick: move |_event| tool_mode.set(ToolMode::Rectangle),
+                onclick: move |_event| app_state.select_tool(ToolMode::Rectangle),
                 Icon {
                     icon: LdRectangleHorizontal,
                 }
             }
+            button {// Polygon
+                onclick: move |_event| app_state.select_tool(ToolMode::Polygon),
+                Icon {
+                    icon: LdPentagon,
+                }
+            }
+            button {// Paint bucket
+                onclick: move |_event| app_state.select_tool(ToolMode::Fill),
+                Icon {
+                    icon: LdPaintBucket,
+                }
+            }
+            button {// Eyedropper, also Alt with the pen
+                title: "Eyedropper (hold Alt while drawing with the pen)",
+                onclick: move |_event| app_state.select_tool(ToolMode::Eyedropper),
+                Icon {
+                    icon: LdPipette,
+                }
+            }
+            select { // FILL MODE, for rectangles, circles and polygons
+                title: "Fill mode",
+                onchange: move |event| fill_mode.set(match event.value().as_str() {
+                    "fill" => FillMode::Fill,
+                    "both" => FillMode::StrokeAndFill,
+                    _ => FillMode::Stroke,
+                }),
+                option { value: "stroke", selected: fill_mode() == FillMode::Stroke, "Stroke only" }
+                option { value: "fill", selected: fill_mode() == FillMode::Fill, "Fill only" }
+                option { value: "both", selected: fill_mode() == FillMode::StrokeAndFill, "Stroke and fill" }
+            }
         }
     }
 }
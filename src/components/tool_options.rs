@@ -0,0 +1,99 @@
+use crate::drawing::{Connectivity, ToolMode};
+use crate::AppState;
+use dioxus::prelude::*;
+use dioxus_free_icons::icons::ld_icons::*;
+use dioxus_free_icons::Icon;
+
+/// Settings for the paint bucket and the eyedropper, and the clipboard for
+/// pixel selections, only shown while the tool they belong to is active.
+#[allow(non_snake_case)]
+pub fn ToolOptions() -> Element {
+    let tool_mode = use_context::<AppState>().tool_mode;
+    let mut bucket_tolerance = use_context::<AppState>().bucket_tolerance;
+    let mut bucket_connectivity = use_context::<AppState>().bucket_connectivity;
+    let mut eyedropper_size = use_context::<AppState>().eyedropper_size;
+    let mut app_state = use_context::<AppState>();
+
+    match tool_mode() {
+        ToolMode::Fill => rsx! {
+            div {
+                class: "stroke-div",
+                div {
+                    class: "stroke-text",
+                    "Fill tolerance: {bucket_tolerance()}"
+                }
+                input {
+                    type: "range",
+                    min: 0,
+                    max: 255,
+                    value: bucket_tolerance(),
+                    oninput: move |event| {
+                        bucket_tolerance.set(event.value().parse::<u8>().unwrap());
+                    },
+                }
+                label {
+                    input {
+                        type: "checkbox",
+                        checked: bucket_connectivity() == Connectivity::Eight,
+                        onchange: move |event| bucket_connectivity.set(match event.checked() {
+                            true => Connectivity::Eight,
+                            false => Connectivity::Four,
+                        }),
+                    }
+                    "Spread through corners"
+                }
+            }
+        },
+        ToolMode::Eyedropper => rsx! {
+            div {
+                class: "stroke-div",
+                div {
+                    class: "stroke-text",
+                    "Sample size"
+                }
+                select {
+                    value: eyedropper_size().to_string(),
+                    onchange: move |event| {
+                        eyedropper_size.set(event.value().parse::<u32>().unwrap());
+                    },
+                    for size in [1, 3, 5] {
+                        option {
+                            value: size.to_string(),
+                            selected: eyedropper_size() == size,
+                            match size {
+                                1 => "Point".to_string(),
+                                _ => format!("{size} by {size} average"),
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        ToolMode::Marquee | ToolMode::Lasso => rsx! {
+            div {
+                class: "stroke-div",
+                button {
+                    title: "Cut",
+                    onclick: move |_event| app_state.cut_pixels(),
+                    Icon { icon: LdScissors }
+                }
+                button {
+                    title: "Copy",
+                    onclick: move |_event| app_state.copy_pixels(),
+                    Icon { icon: LdCopy }
+                }
+                button {
+                    title: "Paste",
+                    onclick: move |_event| app_state.paste_pixels(),
+                    Icon { icon: LdClipboardPaste }
+                }
+                button {
+                    title: "Delete",
+                    onclick: move |_event| app_state.delete_pixels(),
+                    Icon { icon: LdTrash2 }
+                }
+            }
+        },
+        _ => rsx! {},
+    }
+}
@@ -0,0 +1,40 @@
+use crate::app_state::AppState;
+use crate::viewport::ZOOM_STEP;
+use dioxus::prelude::*;
+use dioxus_free_icons::icons::ld_icons::*;
+use dioxus_free_icons::Icon;
+
+/// Zoom buttons over the corner of the canvas. Scroll to zoom, and drag
+/// with the middle button or with Space held to pan.
+#[allow(non_snake_case)]
+pub fn ZoomBar() -> Element {
+    let mut app_state = use_context::<AppState>();
+    let viewport = use_context::<AppState>().viewport;
+
+    rsx! {
+        div {
+            class: "zoom-bar",
+            button {
+                title: "Zoom out",
+                onclick: move |_event| app_state.zoom_by(1.0 / ZOOM_STEP),
+                Icon { icon: LdZoomOut }
+            }
+            button {
+                title: "Zoom to 100%",
+                class: "zoom-level",
+                onclick: move |_event| app_state.zoom_to_actual_size(),
+                "{(viewport().zoom * 100.0).round()}%"
+            }
+            button {
+                title: "Zoom in",
+                onclick: move |_event| app_state.zoom_by(ZOOM_STEP),
+                Icon { icon: LdZoomIn }
+            }
+            button {
+                title: "Zoom to fit",
+                onclick: move |_event| app_state.zoom_to_fit(),
+                Icon { icon: LdMaximize }
+            }
+        }
+    }
+}
@@ -4,29 +4,90 @@ use derive_getters::Dissolve;
 #[allow(unused_imports)]
 use dioxus::logger::tracing;
 use dioxus::prelude::*;
-use dioxus_elements::geometry::ElementPoint;
-use hsv;
+use dioxus_elements::geometry::{ElementPoint, ElementSpace};
 use std::sync::{Arc, Mutex};
 
+use crate::color_space::Hsv;
+use crate::geometry::{corner_handles, points_bounds, rotation_handle, Bounds, HANDLE_RADIUS};
+use crate::layers::{Layer, LayerId};
+use crate::raster;
 use enum_map::Enum;
+use serde::{Deserialize, Serialize};
 
 use std::f64::consts::PI;
+use std::ops::RangeInclusive;
 use web_sys::{
-    wasm_bindgen::JsCast, CanvasRenderingContext2d, HtmlAnchorElement, HtmlCanvasElement,
+    js_sys::{self, Array},
+    wasm_bindgen::{closure::Closure, Clamped, JsCast, JsValue},
+    CanvasRenderingContext2d, HtmlAnchorElement, HtmlCanvasElement, HtmlInputElement, ImageData,
 };
 
 // PUBLIC
-#[derive(Clone, Debug, PartialEq)]
+/// The stroke widths the slider and the shortcuts go between.
+pub const LINE_WIDTHS: RangeInclusive<f64> = 1.0..=10.0;
+
+pub const SELECTION_COLOR: Color = Color(0.0, 120.0, 215.0, 1.0);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Path {
     pub points: Vec<ElementPoint>,
     pub color: Color,
     pub line_width: f64,
 }
 
-#[derive(Clone, Debug, Dissolve, Copy, PartialEq)]
-pub struct Color(pub f64, pub f64, pub f64);
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Polygon {
+    pub points: Vec<ElementPoint>,
+    pub color: Color,
+    pub line_width: f64,
+    pub fill_color: Color,
+    pub fill_mode: FillMode,
+}
+
+/// Red, green and blue from 0 to 255, then alpha from 0 (transparent) to 1 (opaque).
+#[derive(Clone, Debug, Dissolve, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Color(pub f64, pub f64, pub f64, pub f64);
+
+/// Which parts of a closed shape get painted. Lines and pen paths are always stroked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FillMode {
+    #[default]
+    Stroke,
+    Fill,
+    StrokeAndFill,
+}
+
+impl FillMode {
+    pub fn strokes(self) -> bool {
+        self != Self::Fill
+    }
+
+    pub fn fills(self) -> bool {
+        self != Self::Stroke
+    }
+}
 
-#[derive(Debug, Clone, Copy, Enum)]
+/// Which neighbours a flood fill spreads to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Connectivity {
+    /// Edge neighbours only, so the fill can't leak through diagonal gaps.
+    #[default]
+    Four,
+    /// Corner neighbours too.
+    Eight,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FloodFill {
+    /// Where the fill starts; the region is everything connected to the pixel under it.
+    pub point: ElementPoint,
+    pub color: Color,
+    /// How far, per RGBA channel, a pixel may differ from the starting one and still be filled.
+    pub tolerance: u8,
+    pub connectivity: Connectivity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, Serialize, Deserialize)]
 pub enum ToolMode {
     Pen,
     Eraser,
@@ -34,9 +95,101 @@ pub enum ToolMode {
     Circle,
     Rectangle,
     Polygon,
+    Fill,
+    Eyedropper,
+    /// Deletes whole strokes and shapes it touches.
+    ObjectEraser,
+    /// Picks strokes and shapes to move, scale and rotate.
+    Select,
+    /// Selects the pixels in a dragged rectangle.
+    Marquee,
+    /// Selects the pixels in a freehand outline.
+    Lasso,
 }
 
-type Handler = Arc<Mutex<dyn FnMut() -> ()>>;
+impl ToolMode {
+    /// Whether using the tool changes the drawing, as opposed to only looking at it.
+    pub fn draws(self) -> bool {
+        self != Self::Eyedropper
+    }
+
+    /// The canvas cursor the tool switches to, if it has one of its own.
+    pub fn cursor(self) -> Option<&'static str> {
+        match self {
+            Self::Pen | Self::Eyedropper | Self::Marquee | Self::Lasso => Some("crosshair"),
+            Self::Select => Some("default"),
+            _ => None,
+        }
+    }
+}
+
+/// A block of pixels, row-major RGBA like `ImageData`, placed on whole pixels.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PixelPatch {
+    /// Where the top left pixel goes.
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    #[serde(with = "crate::file_format::base64_pixels")]
+    pub pixels: Vec<u8>,
+}
+
+impl PixelPatch {
+    pub fn height(&self) -> u32 {
+        (self.pixels.len() / 4) as u32 / self.width.max(1)
+    }
+
+    pub fn moved(&self, dx: i32, dy: i32) -> Self {
+        Self {
+            x: self.x + dx,
+            y: self.y + dy,
+            ..self.clone()
+        }
+    }
+}
+
+/// What a raster selection did to a layer: first the `cleared` outline is
+/// erased, then the `pasted` pixels are painted over. Cutting only clears,
+/// pasting only pastes, and dragging the selection does both.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RasterPatch {
+    /// A closed outline, empty when nothing was cleared.
+    pub cleared: Vec<ElementPoint>,
+    pub pasted: Option<PixelPatch>,
+}
+
+/// Moves, scales and rotates earlier commands on the same layer, given by
+/// their positions among that layer's entries like `Command::Delete`.
+/// Scaling is uniform, so circles stay circles.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transformation {
+    pub targets: Vec<usize>,
+    /// The point that scaling and rotating keep in place.
+    pub center: ElementPoint,
+    pub scale: f64,
+    /// In radians, clockwise on screen.
+    pub rotation: f64,
+    /// Applied after scaling and rotating.
+    pub offset: euclid::Vector2D<f64, ElementSpace>,
+}
+
+impl Transformation {
+    pub fn apply(&self, point: ElementPoint) -> ElementPoint {
+        let (sin, cos) = self.rotation.sin_cos();
+        let relative = (point - self.center) * self.scale;
+        let rotated = euclid::vec2(
+            relative.x * cos - relative.y * sin,
+            relative.x * sin + relative.y * cos,
+        );
+        self.center + rotated + self.offset
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.scale == 1.0 && self.rotation == 0.0 && self.offset == euclid::vec2(0.0, 0.0)
+    }
+}
+
+type Handler = Arc<Mutex<dyn FnMut()>>;
 #[derive(Clone)]
 pub struct CanvasToolHandler {
     pub onmousedown: Handler,
@@ -44,71 +197,252 @@ pub struct CanvasToolHandler {
     pub onmouseup: Handler,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ShapeProperties {
     pub start_point: ElementPoint,
     pub end_point: ElementPoint,
     pub color: Color,
     pub line_width: f64,
+    pub fill_color: Color,
+    pub fill_mode: FillMode,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Command {
     DrawPath(Path),
     ErasePath(Path),
     DrawLine(ShapeProperties),
     DrawCircle(ShapeProperties),
     DrawRectangle(ShapeProperties),
+    DrawPolygon(Polygon),
+    FloodFill(FloodFill),
     EraseCanvas,
+    /// Takes back a whole earlier command on the same layer, given by its
+    /// position among that layer's entries. Resolved by `layers::layer_commands`
+    /// before anything is replayed.
+    Delete(usize),
+    /// Like `Delete`, resolved before replay by rewriting the commands it targets.
+    Transform(Transformation),
+    Patch(RasterPatch),
+    /// Several commands that undo as one entry. Build it with `Command::group`,
+    /// which keeps groups from nesting.
+    Group(Vec<Command>),
+    /// The layer as the commands it replaced left it, once the history got
+    /// too long to keep them. Always a layer's first entry.
+    Flattened(PixelPatch),
 }
 
 impl Command {
-    pub fn execute(&self, drawing_canvas: &DrawingCanvas) {
+    pub fn execute<T: RenderTarget + ?Sized>(&self, target: &mut T) {
         match self {
             Self::DrawPath(path) => {
-                drawing_canvas.begin_path();
-                path.points.iter().for_each(|point| {
-                    drawing_canvas.draw_point(point, path.color, path.line_width)
-                });
+                target.stroke(Shape::Polyline(&path.points), path.color, path.line_width);
             }
             Self::DrawRectangle(shape_properties) => {
-                drawing_canvas.draw_rect(
-                    &shape_properties.start_point,
-                    &shape_properties.end_point,
-                    &shape_properties.color,
+                paint_shape(
+                    target,
+                    Shape::Rectangle(shape_properties.start_point, shape_properties.end_point),
+                    shape_properties.color,
                     shape_properties.line_width,
+                    shape_properties.fill_color,
+                    shape_properties.fill_mode,
                 );
             }
             Self::DrawCircle(shape_properties) => {
-                drawing_canvas.draw_circle(
-                    &shape_properties.start_point,
-                    &shape_properties.end_point,
-                    &shape_properties.color,
+                paint_shape(
+                    target,
+                    Shape::circle(shape_properties.start_point, shape_properties.end_point),
+                    shape_properties.color,
                     shape_properties.line_width,
+                    shape_properties.fill_color,
+                    shape_properties.fill_mode,
                 );
             }
 
             Self::DrawLine(shape_properties) => {
-                drawing_canvas.draw_line(
-                    &shape_properties.start_point,
-                    &shape_properties.end_point,
-                    &shape_properties.color,
+                target.stroke(
+                    Shape::Line(shape_properties.start_point, shape_properties.end_point),
+                    shape_properties.color,
                     shape_properties.line_width,
                 );
             }
-            Self::ErasePath(path) => {
-                path.points
-                    .iter()
-                    .for_each(|point| drawing_canvas.erase(*point, path.line_width));
+            Self::DrawPolygon(polygon) => {
+                paint_shape(
+                    target,
+                    Shape::Polygon(&polygon.points),
+                    polygon.color,
+                    polygon.line_width,
+                    polygon.fill_color,
+                    polygon.fill_mode,
+                );
+            }
+            Self::FloodFill(flood_fill) => target.flood_fill(flood_fill),
+            Self::Patch(patch) => {
+                if !patch.cleared.is_empty() {
+                    target.set_composite(Composite::DestinationOut);
+                    target.fill(Shape::Polygon(&patch.cleared), Color(0.0, 0.0, 0.0, 1.0));
+                    target.set_composite(Composite::SourceOver);
+                }
+                if let Some(pasted) = &patch.pasted {
+                    target.paste(pasted);
+                }
             }
+            Self::ErasePath(path) => erase_path(target, &path.points, path.line_width),
 
-            Self::EraseCanvas => drawing_canvas.clear_canvas(),
+            // deletions and transformations are already applied to the replay
+            Self::Delete(_) | Self::Transform(_) => {}
+            Self::Group(commands) => commands.iter().for_each(|command| command.execute(target)),
+            Self::Flattened(pixels) => target.paste(pixels),
+            Self::EraseCanvas => target.clear(),
             // _ => {} // good placeholder
         }
     }
+
+    /// The colors this command leaves on the canvas, stroke before fill.
+    pub fn colors(&self) -> Vec<Color> {
+        match self {
+            Self::DrawPath(path) => vec![path.color],
+            Self::DrawLine(shape_properties) => vec![shape_properties.color],
+            Self::DrawCircle(shape_properties) | Self::DrawRectangle(shape_properties) => {
+                shape_colors(
+                    shape_properties.color,
+                    shape_properties.fill_color,
+                    shape_properties.fill_mode,
+                )
+            }
+            Self::DrawPolygon(polygon) => {
+                shape_colors(polygon.color, polygon.fill_color, polygon.fill_mode)
+            }
+            Self::FloodFill(flood_fill) => vec![flood_fill.color],
+            // pasted pixels are left out, there can be any number of colors in them
+            Self::ErasePath(_)
+            | Self::EraseCanvas
+            | Self::Delete(_)
+            | Self::Transform(_)
+            | Self::Patch(_)
+            | Self::Flattened(_) => vec![],
+            Self::Group(commands) => commands.iter().flat_map(Command::colors).collect(),
+        }
+    }
+
+    /// What the history panel calls it.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::DrawPath(_) => "Stroke",
+            Self::ErasePath(_) => "Erase",
+            Self::DrawLine(_) => "Line",
+            Self::DrawCircle(_) => "Circle",
+            Self::DrawRectangle(_) => "Rectangle",
+            Self::DrawPolygon(_) => "Polygon",
+            Self::FloodFill(_) => "Fill",
+            Self::EraseCanvas => "Clear layer",
+            Self::Delete(_) => "Delete",
+            Self::Transform(_) => "Transform",
+            Self::Patch(RasterPatch { pasted: None, .. }) => "Delete pixels",
+            Self::Patch(RasterPatch { cleared, .. }) if cleared.is_empty() => "Paste pixels",
+            Self::Patch(_) => "Move pixels",
+            Self::Flattened(_) => "Earlier history",
+            // a run of the same kind of change reads as that change
+            Self::Group(commands) => match commands.first().map(Command::label) {
+                Some(label) if commands.iter().all(|command| command.label() == label) => label,
+                _ => "Group",
+            },
+        }
+    }
+
+    /// `commands` as a single entry, with the commands of any groups among
+    /// them taken out of those.
+    pub fn group(commands: Vec<Command>) -> Self {
+        Self::Group(
+            commands
+                .into_iter()
+                .flat_map(|command| match command {
+                    Self::Group(commands) => commands,
+                    command => vec![command],
+                })
+                .collect(),
+        )
+    }
+
+    /// The positions of the earlier entries this one deletes or transforms.
+    pub fn targets(&self) -> Vec<usize> {
+        match self {
+            Self::Delete(target) => vec![*target],
+            Self::Transform(transformation) => transformation.targets.clone(),
+            Self::Group(commands) => commands.iter().flat_map(Command::targets).collect(),
+            _ => vec![],
+        }
+    }
 }
-#[derive(Copy, Clone, Debug)]
+
+/// Geometry handed to a [`RenderTarget`], in canvas pixel coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape<'a> {
+    Polyline(&'a [ElementPoint]),
+    /// A polyline whose last point joins back to the first.
+    Polygon(&'a [ElementPoint]),
+    Line(ElementPoint, ElementPoint),
+    /// Opposite corners of the rectangle.
+    Rectangle(ElementPoint, ElementPoint),
+    /// Center and radius.
+    Circle(ElementPoint, f64),
+}
+
+impl Shape<'_> {
+    /// A circle centered on `center` that passes through `edge`, the way the circle tool drags it.
+    pub fn circle(center: ElementPoint, edge: ElementPoint) -> Self {
+        Self::Circle(center, (edge - center).length())
+    }
+}
+
+/// How newly drawn pixels are combined with what is already on the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Composite {
+    /// Paint over the existing pixels.
+    #[default]
+    SourceOver,
+    /// Remove the existing pixels wherever the new shape is drawn.
+    DestinationOut,
+}
+
+/// A surface that commands can be rendered onto.
+///
+/// The web canvas is one implementation; anything that can stroke, fill and
+/// clear shapes can replay a drawing.
+pub trait RenderTarget {
+    fn stroke(&mut self, shape: Shape, color: Color, line_width: f64);
+    fn fill(&mut self, shape: Shape, color: Color);
+    fn clear(&mut self);
+    fn set_composite(&mut self, composite: Composite);
+    /// Fills the region around `fill.point` based on the pixels drawn so far.
+    fn flood_fill(&mut self, fill: &FloodFill);
+    /// Paints `patch` over the pixels drawn so far.
+    fn paste(&mut self, patch: &PixelPatch);
+    /// Everything drawn so far, to be put back with `restore`.
+    fn snapshot(&self) -> PixelPatch;
+    /// Replaces everything drawn with a `snapshot` of the same target.
+    fn restore(&mut self, snapshot: &PixelPatch);
+
+    fn execute_commands(&mut self, commands: &[Command]) {
+        commands.iter().for_each(|command| command.execute(self));
+    }
+}
+
+impl Color {
+    pub fn to_css(self) -> String {
+        let (r, g, b, a) = self.dissolve();
+        format!("rgba({},{},{},{})", r, g, b, a)
+    }
+
+    pub fn with_alpha(self, alpha: f64) -> Self {
+        Self(self.0, self.1, self.2, alpha)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct CanvasProperties {
+    // element ids belong to the running page, not to the drawing
+    #[serde(skip)]
     pub name: &'static str,
     pub width: f64,
     pub height: f64,
@@ -119,40 +453,36 @@ pub trait Canvas {
     fn properties(&self) -> &CanvasProperties;
 
     // shared methods
+    fn element_id(&self) -> String {
+        self.properties().name.to_string()
+    }
+
     fn get_canvas(&self) -> HtmlCanvasElement {
         let document = get_document();
-        let canvas = document
-            .get_element_by_id(&self.properties().name)
+        document
+            .get_element_by_id(&self.element_id())
             .unwrap()
             .dyn_into::<HtmlCanvasElement>()
             .map_err(|_| ())
-            .unwrap();
-
-        canvas
+            .unwrap()
     }
 
     fn get_context(&self) -> CanvasRenderingContext2d {
         let canvas = self.get_canvas();
-        let context = canvas
+        canvas
             .get_context("2d")
             .unwrap()
             .unwrap()
             .dyn_into::<CanvasRenderingContext2d>()
-            .unwrap();
-
-        context
+            .unwrap()
     }
 
-    fn begin_path(&self) {
-        self.get_context().begin_path();
-    }
-    fn draw_point(&self, point: &ElementPoint, color: Color, line_width: f64) {
-        let ctx = self.get_context();
-        let (r, g, b) = color.dissolve();
-        ctx.set_stroke_style_str(&format!("rgb({},{},{})", r, g, b)[..]);
-        ctx.set_line_width(line_width);
-        ctx.line_to(point.x, point.y);
-        ctx.stroke();
+    /// Copies row-major RGBA `pixels`, `width` pixels to a row, onto the canvas at `x`, `y`.
+    fn put_pixels(&self, pixels: &[u8], width: u32, x: f64, y: f64) {
+        let image_data = ImageData::new_with_u8_clamped_array(Clamped(pixels), width).unwrap();
+        self.get_context()
+            .put_image_data(&image_data, x, y)
+            .unwrap();
     }
     fn clear_canvas(&self) {
         let c = self.get_canvas();
@@ -164,106 +494,322 @@ pub trait Canvas {
 #[derive(Copy, Clone, Debug)]
 pub struct DrawingCanvas {
     pub properties: CanvasProperties,
+    pub layer: Option<LayerId>,
+    /// Canvas pixels per pixel of the drawing, so zoomed in layers are drawn
+    /// sharp. `properties` stays in document pixels.
+    pub scale: f64,
 }
 
 impl DrawingCanvas {
-    pub fn erase(&self, point: ElementPoint, line_width: f64) {
-        let ctx = self.get_context();
-        let eraser_size = line_width;
-        ctx.clear_rect(
-            point.x - eraser_size / 2.0,
-            point.y - eraser_size / 2.0,
-            line_width,
-            line_width,
-        );
+    /// The canvas element backing `layer`, stacked under this one's name.
+    pub fn for_layer(&self, layer: LayerId) -> Self {
+        Self {
+            layer: Some(layer),
+            ..*self
+        }
     }
 
-    pub fn draw_rect(
-        &self,
-        start: &ElementPoint,
-        end: &ElementPoint,
-        color: &Color,
-        line_width: f64,
-    ) {
-        let ctx = self.get_context();
-        let (r, g, b) = color.dissolve();
-        ctx.begin_path();
-        ctx.set_line_width(line_width);
-        ctx.set_stroke_style_str(&format!("rgb({},{},{})", r, g, b)[..]);
-        ctx.rect(start.x, start.y, end.x - start.x, end.y - start.y);
-        ctx.stroke();
+    /// The size of the canvas element, in canvas pixels.
+    pub fn canvas_size(&self) -> (f64, f64) {
+        (
+            (self.properties.width * self.scale).round(),
+            (self.properties.height * self.scale).round(),
+        )
     }
 
-    pub fn draw_circle(
-        &self,
-        start: &ElementPoint,
-        end: &ElementPoint,
-        color: &Color,
-        line_width: f64,
-    ) {
+    /// Erases along `points` with a round brush `line_width` across.
+    pub fn erase(&self, points: &[ElementPoint], line_width: f64) {
+        let mut target = *self;
+        erase_path(&mut target, points, line_width);
+    }
+
+    /// Draws a single command on top of what is already there.
+    pub fn draw_command(&self, command: &Command) {
+        let mut target = *self;
+        command.execute(&mut target);
+    }
+
+    pub fn draw_polyline(&self, points: &[ElementPoint], color: &Color, line_width: f64) {
+        self.stroke_shape(Shape::Polyline(points), *color, line_width);
+    }
+
+    /// Draws `patch` over what is there, its pixels scaled up as they are.
+    pub fn draw_pixels(&self, patch: &PixelPatch) {
+        let (source, source_ctx) = scratch_canvas(patch.width, patch.height());
+        let image_data =
+            ImageData::new_with_u8_clamped_array(Clamped(&patch.pixels), patch.width).unwrap();
+        source_ctx.put_image_data(&image_data, 0.0, 0.0).unwrap();
+        let ctx = self.scaled_context();
+        ctx.set_image_smoothing_enabled(false);
+        ctx.draw_image_with_html_canvas_element(&source, patch.x as f64, patch.y as f64)
+            .unwrap();
+    }
+
+    /// The context, set up to draw in document points.
+    fn scaled_context(&self) -> CanvasRenderingContext2d {
         let ctx = self.get_context();
-        let (r, g, b) = color.dissolve();
-        ctx.begin_path();
-        ctx.set_line_width(line_width);
-        ctx.set_stroke_style_str(&format!("rgb({},{},{})", r, g, b)[..]);
-        ctx.arc(
-            start.x,
-            start.y,
-            ((end.x - start.x).powf(2.0) + (end.y - start.y).powf(2.0)).sqrt(),
+        ctx.set_transform(self.scale, 0.0, 0.0, self.scale, 0.0, 0.0)
+            .unwrap();
+        ctx
+    }
+
+    /// The pixels of the drawing in a rectangle, one per document pixel
+    /// whatever the scale. Pixels outside the canvas come back transparent.
+    fn document_pixels(&self, x: f64, y: f64, width: f64, height: f64) -> Vec<u8> {
+        if self.scale == 1.0 {
+            return self
+                .get_context()
+                .get_image_data(x, y, width, height)
+                .unwrap()
+                .data()
+                .0;
+        }
+        let (_, ctx) = scratch_canvas(width as u32, height as u32);
+        let scale = self.scale;
+        ctx.draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+            &self.get_canvas(),
+            x * scale,
+            y * scale,
+            width * scale,
+            height * scale,
             0.0,
-            2.0 * PI,
+            0.0,
+            width,
+            height,
         )
         .unwrap();
-        ctx.stroke();
+        ctx.get_image_data(0.0, 0.0, width, height)
+            .unwrap()
+            .data()
+            .0
     }
-    pub fn draw_line(
-        &self,
-        start: &ElementPoint,
-        end: &ElementPoint,
-        color: &Color,
-        line_width: f64,
-    ) {
-        let ctx = self.get_context();
-        let (r, g, b) = color.dissolve();
-        ctx.begin_path();
+
+    fn stroke_shape(&self, shape: Shape, color: Color, line_width: f64) {
+        let ctx = self.scaled_context();
+        trace_shape(&ctx, shape);
         ctx.set_line_width(line_width);
-        ctx.move_to(start.x, start.y);
-        ctx.set_stroke_style_str(&format!("rgb({},{},{})", r, g, b)[..]);
-        ctx.line_to(end.x, end.y);
+        ctx.set_stroke_style_str(&color.to_css());
         ctx.stroke();
     }
 
-    pub fn save_canvas(&self) {
-        let document = get_document();
-        let c = self.get_canvas();
+    fn fill_shape(&self, shape: Shape, color: Color) {
+        let ctx = self.scaled_context();
+        trace_shape(&ctx, shape);
+        ctx.set_fill_style_str(&color.to_css());
+        ctx.fill();
+    }
+
+    /// The pixels inside the closed `outline`, with everything outside it
+    /// made transparent. None when the outline encloses no pixels.
+    pub fn copy_pixels(&self, outline: &[ElementPoint]) -> Option<PixelPatch> {
+        let (min, max) = points_bounds(outline)?;
+        let (x, y) = (min.x.floor(), min.y.floor());
+        let (width, height) = (max.x.ceil() - x, max.y.ceil() - y);
+        if width < 1.0 || height < 1.0 {
+            return None;
+        }
+        let mut patch = PixelPatch {
+            x: x as i32,
+            y: y as i32,
+            width: width as u32,
+            pixels: self.document_pixels(x, y, width, height),
+        };
+        raster::mask_outside(&mut patch, outline);
+        Some(patch)
+    }
+
+    /// The outline of a raster selection as a dashed line that moves along
+    /// with `phase`, visible on both light and dark pixels.
+    pub fn draw_marching_ants(&self, outline: &[ElementPoint], phase: f64) {
+        let ctx = self.scaled_context();
+        trace_shape(&ctx, Shape::Polygon(outline));
+        ctx.set_line_width(1.0);
+        ctx.set_stroke_style_str("white");
+        ctx.stroke();
+        let dashes = Array::of2(&JsValue::from(4.0), &JsValue::from(4.0));
+        ctx.set_line_dash(&dashes).unwrap();
+        ctx.set_line_dash_offset(-phase);
+        ctx.set_stroke_style_str("black");
+        ctx.stroke();
+        ctx.set_line_dash(&Array::new()).unwrap();
+    }
+
+    /// A thin outline of `bounds`, for rubber-band selection.
+    pub fn draw_box(&self, (min, max): Bounds) {
+        self.stroke_shape(Shape::Rectangle(min, max), SELECTION_COLOR, 1.0);
+    }
+
+    /// The selection box with its scaling handles on the corners and the
+    /// rotation handle above, the handles sized for the screen at `zoom`.
+    pub fn draw_selection(&self, bounds: Bounds, zoom: f64) {
+        self.draw_box(bounds);
+        let rotation = rotation_handle(bounds, zoom);
+        let top = ElementPoint::new(rotation.x, bounds.0.y);
+        self.stroke_shape(Shape::Line(top, rotation), SELECTION_COLOR, 1.0 / zoom);
+        corner_handles(bounds)
+            .into_iter()
+            .chain([rotation])
+            .for_each(|handle| {
+                let shape = Shape::Circle(handle, HANDLE_RADIUS / zoom);
+                self.fill_shape(shape, Color(255.0, 255.0, 255.0, 1.0));
+                self.stroke_shape(shape, SELECTION_COLOR, 1.0 / zoom);
+            });
+    }
+
+    /// The average color of the `size` by `size` pixels around `point`, as it
+    /// shows on screen: the visible layers composited over the white page.
+    pub fn sample_color(&self, layers: &[Layer], point: ElementPoint, size: u32) -> Color {
+        let half = (size / 2) as f64;
+        let (x, y) = (point.x.floor() - half, point.y.floor() - half);
+        let mut pixels = [255; 4].repeat((size * size) as usize);
+        layers
+            .iter()
+            .filter(|layer| layer.visible)
+            .for_each(|layer| {
+                let layer_pixels =
+                    self.for_layer(layer.id)
+                        .document_pixels(x, y, size as f64, size as f64);
+                raster::composite(&mut pixels, &layer_pixels, layer.opacity);
+            });
+
+        let count = (size * size) as f64;
+        let average = |channel: usize| {
+            pixels
+                .iter()
+                .skip(channel)
+                .step_by(4)
+                .map(|&value| value as f64)
+                .sum::<f64>()
+                / count
+        };
+        Color(average(0), average(1), average(2), 1.0)
+    }
+
+    /// Flattens the visible layers, bottom to top, into one PNG download.
+    pub fn save_canvas(&self, layers: &[Layer]) {
+        let (width, height) = (self.properties.width, self.properties.height);
+        let (c, ctx) = scratch_canvas(width as u32, height as u32);
+        layers
+            .iter()
+            .filter(|layer| layer.visible)
+            .for_each(|layer| {
+                ctx.set_global_alpha(layer.opacity);
+                // the layers may be drawn larger while zoomed in
+                ctx.draw_image_with_html_canvas_element_and_dw_and_dh(
+                    &self.for_layer(layer.id).get_canvas(),
+                    0.0,
+                    0.0,
+                    width,
+                    height,
+                )
+                .unwrap();
+            });
 
         let img_url = c.to_data_url().unwrap();
-        let img_link = document
-            .create_element("a")
-            .unwrap()
-            .dyn_into::<HtmlAnchorElement>()
+        download(&img_url, "image.png");
+    }
+
+    /// The visible layers, bottom to top, shrunk to `width` pixels across,
+    /// as a PNG data URL. Each one is drawn on this canvas by `draw` first.
+    pub fn thumbnail(
+        &mut self,
+        layers: &[Layer],
+        width: f64,
+        mut draw: impl FnMut(&mut Self, LayerId),
+    ) -> String {
+        let height = (width * self.properties.height / self.properties.width).max(1.0);
+        let (c, ctx) = scratch_canvas(width as u32, height as u32);
+        layers
+            .iter()
+            .filter(|layer| layer.visible)
+            .for_each(|layer| {
+                draw(self, layer.id);
+                ctx.set_global_alpha(layer.opacity);
+                ctx.draw_image_with_html_canvas_element_and_dw_and_dh(
+                    &self.get_canvas(),
+                    0.0,
+                    0.0,
+                    width,
+                    height,
+                )
+                .unwrap();
+            });
+        c.to_data_url().unwrap()
+    }
+}
+
+impl RenderTarget for DrawingCanvas {
+    fn stroke(&mut self, shape: Shape, color: Color, line_width: f64) {
+        self.stroke_shape(shape, color, line_width);
+    }
+
+    fn fill(&mut self, shape: Shape, color: Color) {
+        self.fill_shape(shape, color);
+    }
+
+    fn clear(&mut self) {
+        self.clear_canvas();
+    }
+
+    fn set_composite(&mut self, composite: Composite) {
+        let operation = match composite {
+            Composite::SourceOver => "source-over",
+            Composite::DestinationOut => "destination-out",
+        };
+        self.get_context()
+            .set_global_composite_operation(operation)
             .unwrap();
+    }
 
-        img_link.set_href(&img_url[..]);
-        img_link.set_download("image.png");
-        img_link.click();
-        img_link.remove();
+    fn flood_fill(&mut self, fill: &FloodFill) {
+        // filled in document pixels, so the region comes out the same at any
+        // zoom and like the raster's, then drawn scaled up like pasted pixels
+        let (width, height) = (self.properties.width, self.properties.height);
+        let pixels = self.document_pixels(0.0, 0.0, width, height);
+        self.draw_pixels(&raster::fill_patch(
+            &pixels,
+            width as usize,
+            height as usize,
+            fill,
+        ));
     }
 
-    pub fn execute_commands(&self, commands: Vec<Command>) {
-        match commands.is_empty() {
-            true => {}
-            false => {
-                commands.iter().for_each(|command| command.execute(&self));
-            }
+    fn paste(&mut self, patch: &PixelPatch) {
+        self.draw_pixels(patch);
+    }
+
+    fn snapshot(&self) -> PixelPatch {
+        let (width, height) = self.canvas_size();
+        PixelPatch {
+            x: 0,
+            y: 0,
+            width: width as u32,
+            pixels: self
+                .get_context()
+                .get_image_data(0.0, 0.0, width, height)
+                .unwrap()
+                .data()
+                .0,
         }
     }
+
+    fn restore(&mut self, snapshot: &PixelPatch) {
+        // putting image data replaces the pixels rather than blending with them
+        self.put_pixels(&snapshot.pixels, snapshot.width, 0.0, 0.0);
+    }
 }
 
 impl Canvas for DrawingCanvas {
     fn properties(&self) -> &CanvasProperties {
         &self.properties
     }
+
+    fn element_id(&self) -> String {
+        match self.layer {
+            Some(layer) => format!("{}-{}", self.properties.name, layer),
+            None => self.properties.name.to_string(),
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -274,46 +820,33 @@ pub struct SatValCanvas {
 impl SatValCanvas {
     pub fn draw_color_picker(&self, hue: f64) {
         let c = self.get_canvas();
-        (0..c.width()).for_each(|px_x| {
-            (0..c.height()).for_each(|px_y| {
-                let (r, g, b): (u8, u8, u8) = hsv::hsv_to_rgb(
-                    hue,
-                    (px_x as f64) / (c.width() as f64),
-                    (px_y as f64) / (c.height() as f64),
-                );
-
-                let ctx = self.get_context();
-                let fill_style = &format!("rgb({},{},{})", r, g, b)[..];
-
-                ctx.set_fill_style_str(fill_style);
-                ctx.fill_rect(px_x as f64, (c.height() - 1 - px_y) as f64, 1.0, 1.0);
-            });
-        });
+        let pixels = sat_val_pixels(c.width(), c.height(), hue);
+        self.put_pixels(&pixels, c.width(), 0.0, 0.0);
     }
 
-    pub fn draw_sat_val_pointer(&self, sat_val_coord: (f64, f64)) {
+    pub fn draw_sat_val_pointer(&self, hsv: Hsv) {
         let ctx = self.get_context();
         ctx.begin_path();
         ctx.arc(
-            sat_val_coord.0,
-            sat_val_coord.1,
+            hsv.saturation * self.properties.width,
+            (1.0 - hsv.value) * self.properties.height,
             3.0, // radius of circle
             0.0,
             2.0 * PI,
         )
         .unwrap();
 
-        ctx.set_stroke_style_str(&format!("rgb(255,255,255)")[..]);
+        ctx.set_stroke_style_str("rgb(255,255,255)");
         ctx.stroke();
     }
 
-    pub fn read_color(self, sat_val_coord: (f64, f64)) -> Color {
-        let color = self
-            .get_context()
-            .get_image_data(sat_val_coord.0, sat_val_coord.1, 1.0, 1.0)
-            .unwrap()
-            .data();
-        Color(color[0] as f64, color[1] as f64, color[2] as f64)
+    /// The saturation and value at `point` on the square, for the given `hue`.
+    pub fn hsv_at(&self, point: ElementPoint, hue: f64) -> Hsv {
+        Hsv {
+            hue,
+            saturation: (point.x / self.properties.width).clamp(0.0, 1.0),
+            value: (1.0 - point.y / self.properties.height).clamp(0.0, 1.0),
+        }
     }
 }
 
@@ -337,40 +870,367 @@ impl HueCanvas {
 
         ctx.set_fill_style_str("rgb(0,0,0)");
         ctx.fill_rect(
-            hue * (c.width() as f64 / 360.0) as f64,
+            hue * (c.width() as f64 / 360.0),
             0.0,
             2.0,
             (c.height() / 2) as f64,
         )
     }
 
+    pub fn hue_at(&self, point: ElementPoint) -> f64 {
+        (point.x / self.properties.width * 360.0).clamp(0.0, 360.0)
+    }
+
     pub fn draw_hue_bar(&self, hue: f64) {
+        let c = self.get_canvas();
+        // the top half is left for the pointer
+        let top = c.height() / 2 + 1;
+        let pixels = hue_bar_pixels(c.width(), c.height() - top);
+        self.put_pixels(&pixels, c.width(), 0.0, top as f64);
+        self.draw_hue_pointer(hue);
+    }
+}
+
+impl Canvas for HueCanvas {
+    fn properties(&self) -> &CanvasProperties {
+        &self.properties
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct AlphaCanvas {
+    pub properties: CanvasProperties,
+}
+
+impl AlphaCanvas {
+    /// `color` fading in from transparent over a checkerboard, with a pointer at its alpha.
+    pub fn draw_alpha_bar(&self, color: Color) {
         let c = self.get_canvas();
         let ctx = self.get_context();
+        let (width, half) = (c.width() as f64, (c.height() / 2) as f64);
 
+        ctx.clear_rect(0.0, 0.0, width, c.height() as f64);
+        (0..c.width()).step_by(5).for_each(|px_x| {
+            (0..2).for_each(|row| {
+                let light = (px_x / 5 + row) % 2 == 0;
+                ctx.set_fill_style_str(if light {
+                    "rgb(255,255,255)"
+                } else {
+                    "rgb(200,200,200)"
+                });
+                ctx.fill_rect(
+                    px_x as f64,
+                    half + 1.0 + row as f64 * half / 2.0,
+                    5.0,
+                    half / 2.0,
+                );
+            });
+        });
         (0..c.width()).for_each(|px_x| {
-            let (r, g, b): (u8, u8, u8) =
-                hsv::hsv_to_rgb(360.0 * ((px_x as f64) / (c.width() as f64)), 1.0, 1.0);
-            ctx.set_fill_style_str(&format!("rgb({},{},{})", r, g, b)[..]);
-            ctx.fill_rect(
-                px_x as f64,
-                (c.height() / 2) as f64 + 1.0,
-                1.0,
-                (c.height() / 2) as f64,
-            );
+            ctx.set_fill_style_str(&color.with_alpha(px_x as f64 / width).to_css());
+            ctx.fill_rect(px_x as f64, half + 1.0, 1.0, half);
         });
-        self.draw_hue_pointer(hue);
+
+        ctx.set_fill_style_str("rgb(0,0,0)");
+        ctx.fill_rect(color.3 * width, 0.0, 2.0, half);
     }
 }
 
-impl Canvas for HueCanvas {
+impl Canvas for AlphaCanvas {
     fn properties(&self) -> &CanvasProperties {
         &self.properties
     }
 }
 
+/// The saturation/value square for `hue` as RGBA pixels: saturation grows to
+/// the right and value towards the top. Each pixel shows the color at its
+/// center, the same one `SatValCanvas::hsv_at` picks there.
+pub fn sat_val_pixels(width: u32, height: u32, hue: f64) -> Vec<u8> {
+    (0..height)
+        .flat_map(|px_y| {
+            let value = 1.0 - (px_y as f64 + 0.5) / height as f64;
+            (0..width).flat_map(move |px_x| {
+                let saturation = (px_x as f64 + 0.5) / width as f64;
+                opaque_pixel(Hsv {
+                    hue,
+                    saturation,
+                    value,
+                })
+            })
+        })
+        .collect()
+}
+
+/// Every hue from 0 to 360 degrees left to right, fully saturated, as RGBA pixels.
+pub fn hue_bar_pixels(width: u32, height: u32) -> Vec<u8> {
+    let row: Vec<u8> = (0..width)
+        .flat_map(|px_x| {
+            let hue = 360.0 * (px_x as f64 + 0.5) / width as f64;
+            opaque_pixel(Hsv {
+                hue,
+                saturation: 1.0,
+                value: 1.0,
+            })
+        })
+        .collect();
+    row.repeat(height as usize)
+}
+
+pub fn save_text_file(contents: &str, mime_type: &str, file_name: &str) {
+    let encoded = web_sys::js_sys::encode_uri_component(contents);
+    download(
+        &format!("data:{};charset=utf-8,{}", mime_type, encoded),
+        file_name,
+    );
+}
+
+/// Name and text of the file picked in a file input.
+pub async fn read_chosen_file(event: &Event<FormData>) -> Option<(String, String)> {
+    let files = event.files()?;
+    let file_name = files.files().into_iter().next()?;
+    let contents = files.read_file_to_string(&file_name).await?;
+    Some((file_name, contents))
+}
+
+/// Whether a text field has the keyboard, in which case shortcuts stay out of its way.
+pub fn typing_in_field() -> bool {
+    let Some(element) = get_document().active_element() else {
+        return false;
+    };
+    match element.tag_name().as_str() {
+        "TEXTAREA" => true,
+        "INPUT" => element
+            .dyn_into::<HtmlInputElement>()
+            .is_ok_and(|input| matches!(input.type_().as_str(), "text" | "number" | "search")),
+        _ => false,
+    }
+}
+
+/// The size of the element with `id` on the page, inside its border.
+pub fn element_size(id: &str) -> Option<(f64, f64)> {
+    let element = get_document().get_element_by_id(id)?;
+    Some((
+        element.client_width() as f64,
+        element.client_height() as f64,
+    ))
+}
+
+/// A setting saved by `store_setting`, if the browser kept it.
+pub fn load_setting(key: &str) -> Option<String> {
+    web_sys::window()?
+        .local_storage()
+        .ok()??
+        .get_item(key)
+        .ok()?
+}
+
+/// Keeps `value` in the browser's local storage, where it survives reloads.
+pub fn store_setting(key: &str, value: &str) {
+    let storage = web_sys::window().and_then(|window| window.local_storage().ok().flatten());
+    if let Some(storage) = storage {
+        // a full or disabled storage only means the setting isn't remembered
+        let _ = storage.set_item(key, value);
+    }
+}
+
+pub fn ask_confirmation(message: &str) -> bool {
+    web_sys::window()
+        .unwrap()
+        .confirm_with_message(message)
+        .unwrap()
+}
+
+pub fn show_message(message: &str) {
+    web_sys::window()
+        .unwrap()
+        .alert_with_message(message)
+        .unwrap();
+}
+
 // PRIVATE
 
+fn download(url: &str, file_name: &str) {
+    let link = get_document()
+        .create_element("a")
+        .unwrap()
+        .dyn_into::<HtmlAnchorElement>()
+        .unwrap();
+
+    link.set_href(url);
+    link.set_download(file_name);
+    link.click();
+    link.remove();
+}
+
+fn shape_colors(color: Color, fill_color: Color, fill_mode: FillMode) -> Vec<Color> {
+    [
+        (fill_mode.strokes(), color),
+        (fill_mode.fills(), fill_color),
+    ]
+    .into_iter()
+    .filter_map(|(painted, color)| painted.then_some(color))
+    .collect()
+}
+
+/// Fills before stroking, so the whole stroke width stays visible.
+fn paint_shape<T: RenderTarget + ?Sized>(
+    target: &mut T,
+    shape: Shape,
+    color: Color,
+    line_width: f64,
+    fill_color: Color,
+    fill_mode: FillMode,
+) {
+    if fill_mode.fills() {
+        target.fill(shape, fill_color);
+    }
+    if fill_mode.strokes() {
+        target.stroke(shape, color, line_width);
+    }
+}
+
+/// A round brush dragged along `points`: a disc on every point and the
+/// straight band between each pair, so fast strokes leave no gaps. Erasing is
+/// all or nothing, so the overlaps don't matter.
+fn erase_path<T: RenderTarget + ?Sized>(target: &mut T, points: &[ElementPoint], line_width: f64) {
+    let opaque = Color(0.0, 0.0, 0.0, 1.0);
+    target.set_composite(Composite::DestinationOut);
+    points
+        .windows(2)
+        .for_each(|segment| target.stroke(Shape::Line(segment[0], segment[1]), opaque, line_width));
+    points
+        .iter()
+        .for_each(|point| target.fill(Shape::Circle(*point, line_width / 2.0), opaque));
+    target.set_composite(Composite::SourceOver);
+}
+
+fn trace_shape(ctx: &CanvasRenderingContext2d, shape: Shape) {
+    ctx.begin_path();
+    match shape {
+        Shape::Polyline(points) => points
+            .iter()
+            .for_each(|point| ctx.line_to(point.x, point.y)),
+        Shape::Polygon(points) => {
+            points
+                .iter()
+                .for_each(|point| ctx.line_to(point.x, point.y));
+            ctx.close_path();
+        }
+        Shape::Line(start, end) => {
+            ctx.move_to(start.x, start.y);
+            ctx.line_to(end.x, end.y);
+        }
+        Shape::Rectangle(start, end) => {
+            ctx.rect(start.x, start.y, end.x - start.x, end.y - start.y)
+        }
+        Shape::Circle(center, radius) => {
+            ctx.arc(center.x, center.y, radius, 0.0, 2.0 * PI).unwrap()
+        }
+    }
+}
+
+fn opaque_pixel(hsv: Hsv) -> [u8; 4] {
+    let color = hsv.to_rgb(1.0);
+    [
+        color.0.round() as u8,
+        color.1.round() as u8,
+        color.2.round() as u8,
+        255,
+    ]
+}
+
+/// Calls `callback` every `milliseconds` for as long as the page is open.
+pub fn every(milliseconds: i32, callback: impl FnMut() + 'static) {
+    let closure = Closure::<dyn FnMut()>::new(callback);
+    web_sys::window()
+        .unwrap()
+        .set_interval_with_callback_and_timeout_and_arguments_0(
+            closure.as_ref().unchecked_ref(),
+            milliseconds,
+        )
+        .unwrap();
+    // the interval keeps calling it, so it has to outlive this function
+    closure.forget();
+}
+
+/// Milliseconds since the epoch.
+pub fn now() -> f64 {
+    js_sys::Date::now()
+}
+
+/// A canvas outside the page, to work on pixels with.
+fn scratch_canvas(width: u32, height: u32) -> (HtmlCanvasElement, CanvasRenderingContext2d) {
+    let canvas = get_document()
+        .create_element("canvas")
+        .unwrap()
+        .dyn_into::<HtmlCanvasElement>()
+        .unwrap();
+    canvas.set_width(width);
+    canvas.set_height(height);
+    let ctx = canvas
+        .get_context("2d")
+        .unwrap()
+        .unwrap()
+        .dyn_into::<CanvasRenderingContext2d>()
+        .unwrap();
+    (canvas, ctx)
+}
+
 fn get_document() -> web_sys::Document {
     web_sys::window().unwrap().document().unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u32 = 256;
+
+    fn pixel(pixels: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * width + x) * 4) as usize;
+        pixels[i..i + 4].try_into().unwrap()
+    }
+
+    /// Pixels are sampled at their centers, so the edges are a step short of the exact color.
+    fn assert_close(actual: [u8; 4], expected: [u8; 4]) {
+        let close = actual
+            .iter()
+            .zip(expected)
+            .all(|(actual, expected)| actual.abs_diff(expected) <= 4);
+        assert!(close, "{:?} is not close to {:?}", actual, expected);
+    }
+
+    #[test]
+    fn sat_val_corners() {
+        let pixels = sat_val_pixels(SIZE, SIZE, 120.0);
+        let last = SIZE - 1;
+        assert_close(pixel(&pixels, SIZE, 0, 0), [255, 255, 255, 255]);
+        assert_close(pixel(&pixels, SIZE, last, 0), [0, 255, 0, 255]);
+        assert_close(pixel(&pixels, SIZE, 0, last), [0, 0, 0, 255]);
+        assert_close(pixel(&pixels, SIZE, last, last), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn sat_val_edges() {
+        let pixels = sat_val_pixels(SIZE, SIZE, 240.0);
+        let (middle, last) = (SIZE / 2, SIZE - 1);
+        // the top row runs from white to the hue, the bottom row is black throughout
+        assert_close(pixel(&pixels, SIZE, middle, 0), [128, 128, 255, 255]);
+        assert_close(pixel(&pixels, SIZE, middle, last), [0, 0, 0, 255]);
+        // the left column runs from white to black through grays
+        assert_close(pixel(&pixels, SIZE, 0, middle), [127, 127, 127, 255]);
+        assert_close(pixel(&pixels, SIZE, last, middle), [0, 0, 127, 255]);
+    }
+
+    #[test]
+    fn hue_bar_is_red_at_both_ends() {
+        let pixels = hue_bar_pixels(SIZE, 2);
+        let last = SIZE - 1;
+        for y in 0..2 {
+            assert_close(pixel(&pixels, SIZE, 0, y), [255, 0, 0, 255]);
+            assert_close(pixel(&pixels, SIZE, last, y), [255, 0, 0, 255]);
+        }
+        assert_close(pixel(&pixels, SIZE, SIZE / 3, 0), [0, 255, 0, 255]);
+        assert_close(pixel(&pixels, SIZE, SIZE * 2 / 3, 0), [0, 0, 255, 255]);
+    }
+}
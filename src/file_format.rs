@@ -0,0 +1,444 @@
+// file_format.rs
+//
+// The native file format: the canvas and its full command history as JSON.
+// The history is saved as the whole tree, so branches left behind by drawing
+// after an undo can still be jumped back to once the file is opened again.
+// Every file records the version it was written with. Older files are
+// upgraded one version at a time through `MIGRATIONS` before they are
+// deserialized, so the structs below only ever describe the current version.
+//
+// Pixels are written as base64 strings rather than arrays of numbers, which
+// would take up to four times the space.
+
+use crate::drawing::CanvasProperties;
+use crate::history::SavedHistory;
+use crate::layers::Layer;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+
+pub const CURRENT_VERSION: u64 = 6;
+pub const FILE_EXTENSION: &str = "draw.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    pub version: u64,
+    pub canvas: CanvasProperties,
+    /// Bottom to top.
+    pub layers: Vec<Layer>,
+    pub history: SavedHistory,
+}
+
+#[derive(Debug)]
+pub enum DocumentError {
+    Json(serde_json::Error),
+    MissingVersion,
+    UnsupportedVersion(u64),
+    Migration { from: u64, reason: String },
+    InvalidHistory(String),
+}
+
+impl fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Json(error) => write!(f, "not a valid drawing file: {}", error),
+            Self::MissingVersion => write!(f, "the file has no schema version"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported schema version {} (this app reads 1 to {})",
+                version, CURRENT_VERSION
+            ),
+            Self::Migration { from, reason } => {
+                write!(f, "could not upgrade from schema {}: {}", from, reason)
+            }
+            Self::InvalidHistory(reason) => write!(f, "the history is broken: {}", reason),
+        }
+    }
+}
+
+/// How `PixelPatch` pixels are serialized: one base64 string.
+pub mod base64_pixels {
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(pixels: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64_STANDARD.encode(pixels))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        BASE64_STANDARD.decode(encoded).map_err(D::Error::custom)
+    }
+}
+
+impl From<serde_json::Error> for DocumentError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Json(error)
+    }
+}
+
+impl Document {
+    pub fn new(canvas: CanvasProperties, layers: Vec<Layer>, history: SavedHistory) -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            canvas,
+            layers,
+            history,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, DocumentError> {
+        let value = serde_json::from_str::<Value>(json)?;
+        let value = migrate(value)?;
+        let document: Self = serde_json::from_value(value)?;
+        document
+            .history
+            .check()
+            .map_err(DocumentError::InvalidHistory)?;
+        Ok(document)
+    }
+}
+
+// PRIVATE
+
+type Migration = fn(Value) -> Result<Value, String>;
+
+/// `MIGRATIONS[n]` turns a version `n + 1` document into a version `n + 2`
+/// document. Bump `CURRENT_VERSION` and append here whenever the serialized
+/// shape of the history changes.
+const MIGRATIONS: [Migration; (CURRENT_VERSION - 1) as usize] =
+    [add_layers, add_fills, add_alpha, encode_pixels, save_tree];
+
+fn migrate(mut value: Value) -> Result<Value, DocumentError> {
+    let version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or(DocumentError::MissingVersion)?;
+
+    if version == 0 || version > CURRENT_VERSION {
+        return Err(DocumentError::UnsupportedVersion(version));
+    }
+
+    for (from, migration) in (version..).zip(&MIGRATIONS[(version - 1) as usize..]) {
+        value = migration(value).map_err(|reason| DocumentError::Migration { from, reason })?;
+        value["version"] = Value::from(from + 1);
+    }
+    Ok(value)
+}
+
+// Migrations write out literal JSON rather than going through the current
+// structs, so they keep producing the version they were written for.
+
+/// Version 2 added layers. A version 1 drawing becomes a single layer.
+fn add_layers(mut value: Value) -> Result<Value, String> {
+    value["layers"] = json!([{
+        "id": 0,
+        "name": "Layer 1",
+        "visible": true,
+        "locked": false,
+        "opacity": 1.0,
+    }]);
+    for key in ["undo_commands", "redo_commands"] {
+        let commands = value
+            .get_mut(key)
+            .and_then(Value::as_array_mut)
+            .ok_or(format!("{} is missing", key))?;
+        commands.iter_mut().for_each(|command| {
+            *command = json!({ "layer": 0, "command": command.take() });
+        });
+    }
+    Ok(value)
+}
+
+/// Version 3 added fills to lines and closed shapes. Everything drawn before
+/// was stroke only.
+fn add_fills(mut value: Value) -> Result<Value, String> {
+    for key in ["undo_commands", "redo_commands"] {
+        let entries = value
+            .get_mut(key)
+            .and_then(Value::as_array_mut)
+            .ok_or(format!("{} is missing", key))?;
+        // commands are serialized as `{"DrawRectangle": {...}}`
+        let commands = entries
+            .iter_mut()
+            .filter_map(|entry| entry.get_mut("command").and_then(Value::as_object_mut));
+        for command in commands {
+            for (kind, shape) in command.iter_mut() {
+                let Some(shape) = shape.as_object_mut() else {
+                    continue;
+                };
+                if ["DrawLine", "DrawCircle", "DrawRectangle", "DrawPolygon"]
+                    .contains(&kind.as_str())
+                {
+                    shape.insert("fill_color".to_string(), json!([255.0, 255.0, 255.0]));
+                    shape.insert("fill_mode".to_string(), json!("Stroke"));
+                }
+            }
+        }
+    }
+    Ok(value)
+}
+
+/// Version 4 added alpha to colors. Everything drawn before was opaque.
+fn add_alpha(mut value: Value) -> Result<Value, String> {
+    for key in ["undo_commands", "redo_commands"] {
+        let entries = value
+            .get_mut(key)
+            .and_then(Value::as_array_mut)
+            .ok_or(format!("{} is missing", key))?;
+        let shapes = entries
+            .iter_mut()
+            .filter_map(|entry| entry.get_mut("command").and_then(Value::as_object_mut))
+            .flat_map(|command| command.values_mut())
+            .filter_map(Value::as_object_mut);
+        for shape in shapes {
+            for color in ["color", "fill_color"] {
+                if let Some(channels) = shape.get_mut(color).and_then(Value::as_array_mut) {
+                    channels.push(json!(1.0));
+                }
+            }
+        }
+    }
+    Ok(value)
+}
+
+/// Version 5 wrote pixels, of flattened history and of pasted or moved
+/// selections, as base64 instead of arrays of numbers.
+fn encode_pixels(mut value: Value) -> Result<Value, String> {
+    for key in ["undo_commands", "redo_commands"] {
+        let entries = value
+            .get_mut(key)
+            .and_then(Value::as_array_mut)
+            .ok_or(format!("{} is missing", key))?;
+        for entry in entries {
+            encode_pixels_in(entry)?;
+        }
+    }
+    Ok(value)
+}
+
+/// Encodes every `pixels` array in `value`, however deep, since patches can
+/// be inside groups.
+fn encode_pixels_in(value: &mut Value) -> Result<(), String> {
+    match value {
+        Value::Object(object) => {
+            for (key, field) in object.iter_mut() {
+                match field.as_array() {
+                    Some(pixels) if key == "pixels" => {
+                        let bytes = pixels
+                            .iter()
+                            .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                            .collect::<Option<Vec<u8>>>()
+                            .ok_or("pixels has to be bytes")?;
+                        *field = Value::from(BASE64_STANDARD.encode(bytes));
+                    }
+                    _ => encode_pixels_in(field)?,
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                encode_pixels_in(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Version 6 saved the whole history tree instead of only what undo and redo
+/// reach. The undo and redo stacks become a single branch.
+fn save_tree(mut value: Value) -> Result<Value, String> {
+    let mut take = |key: &str| match value.as_object_mut().and_then(|object| object.remove(key)) {
+        Some(Value::Array(entries)) => Ok(entries),
+        _ => Err(format!("{} is missing", key)),
+    };
+    let undo = take("undo_commands")?;
+    let redo = take("redo_commands")?;
+    let current = undo.len().checked_sub(1);
+    // redo is a stack, with the next entry last
+    let entries: Vec<Value> = undo.into_iter().chain(redo.into_iter().rev()).collect();
+    let count = entries.len();
+    let nodes: Vec<Value> = entries
+        .into_iter()
+        .enumerate()
+        .map(|(node, entry)| {
+            json!({
+                "parent": node.checked_sub(1),
+                "redo": Some(node + 1).filter(|next| *next < count),
+                "entry": entry,
+            })
+        })
+        .collect();
+    value["history"] = json!({
+        "nodes": nodes,
+        "root_redo": Some(0).filter(|_| count > 0),
+        "current": current,
+    });
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drawing::{Command, PixelPatch, RasterPatch};
+    use crate::history::History;
+    use crate::layers::LayerCommand;
+
+    fn patch() -> PixelPatch {
+        PixelPatch {
+            x: 1,
+            y: 2,
+            width: 1,
+            pixels: vec![255, 0, 128, 255, 0, 0, 0, 0],
+        }
+    }
+
+    fn entry(command: Command) -> LayerCommand {
+        LayerCommand { layer: 0, command }
+    }
+
+    fn document(history: &History) -> Document {
+        Document::new(
+            CanvasProperties {
+                name: "",
+                width: 4.0,
+                height: 4.0,
+            },
+            vec![Layer::new(0, "Layer 1".to_string())],
+            history.saved(),
+        )
+    }
+
+    #[test]
+    fn pixels_are_saved_as_base64() {
+        let mut history = History::default();
+        history.record(entry(Command::Flattened(patch())));
+        let document = document(&history);
+        let json = document.to_json();
+        assert!(json.contains(r#""pixels":"/wCA/wAAAAA=""#), "{}", json);
+        let opened = Document::from_json(&json).unwrap();
+        assert_eq!(opened.history, document.history);
+    }
+
+    #[test]
+    fn abandoned_branches_are_saved() {
+        let mut history = History::default();
+        history.record(entry(Command::EraseCanvas));
+        history.record(entry(Command::Delete(0)));
+        history.undo();
+        history.record(entry(Command::Flattened(patch())));
+        history.undo();
+
+        let opened = Document::from_json(&document(&history).to_json()).unwrap();
+        let mut opened = History::from_saved(opened.history);
+        assert_eq!(opened.saved(), history.saved());
+        assert_eq!(opened.entries_to(Some(1)), history.entries_to(Some(1)));
+        assert_eq!(opened.redo(), Some(&entry(Command::Flattened(patch()))));
+    }
+
+    #[test]
+    fn broken_histories_are_refused() {
+        let mut history = History::default();
+        history.record(entry(Command::EraseCanvas));
+        let mut document = document(&history);
+        document.history.nodes[0].parent = Some(3);
+        assert!(matches!(
+            Document::from_json(&document.to_json()),
+            Err(DocumentError::InvalidHistory(_))
+        ));
+    }
+
+    #[test]
+    fn version_5_undo_and_redo_become_one_branch() {
+        let json = json!({
+            "version": 5,
+            "canvas": { "width": 4.0, "height": 4.0 },
+            "layers": [{ "id": 0, "name": "Layer 1", "visible": true, "locked": false, "opacity": 1.0 }],
+            "undo_commands": [
+                { "layer": 0, "command": "EraseCanvas" },
+                { "layer": 0, "command": { "Delete": 0 } },
+            ],
+            "redo_commands": [
+                { "layer": 0, "command": { "Delete": 2 } },
+                { "layer": 0, "command": { "Delete": 1 } },
+            ],
+        });
+        let document = Document::from_json(&json.to_string()).unwrap();
+        assert_eq!(document.version, CURRENT_VERSION);
+        let mut history = History::from_saved(document.history);
+        assert_eq!(
+            history.undo_commands(),
+            [entry(Command::EraseCanvas), entry(Command::Delete(0))]
+        );
+        // the redo stack had the next entry last
+        assert_eq!(history.redo(), Some(&entry(Command::Delete(1))));
+        assert_eq!(history.redo(), Some(&entry(Command::Delete(2))));
+        assert_eq!(history.redo(), None);
+    }
+
+    #[test]
+    fn version_5_without_history_opens_blank() {
+        let json = json!({
+            "version": 5,
+            "canvas": { "width": 4.0, "height": 4.0 },
+            "layers": [],
+            "undo_commands": [],
+            "redo_commands": [],
+        });
+        let document = Document::from_json(&json.to_string()).unwrap();
+        let history = History::from_saved(document.history);
+        assert_eq!(history.current(), None);
+        assert!(history.nodes().is_empty());
+    }
+
+    #[test]
+    fn version_4_pixel_arrays_are_encoded() {
+        let json = json!({
+            "version": 4,
+            "canvas": { "width": 4.0, "height": 4.0 },
+            "layers": [{ "id": 0, "name": "Layer 1", "visible": true, "locked": false, "opacity": 1.0 }],
+            "undo_commands": [
+                { "layer": 0, "command": { "Flattened": { "x": 1, "y": 2, "width": 1, "pixels": [255, 0, 128, 255, 0, 0, 0, 0] } } },
+            ],
+            "redo_commands": [
+                { "layer": 0, "command": { "Group": [
+                    { "Patch": { "cleared": [], "pasted": { "x": 1, "y": 2, "width": 1, "pixels": [255, 0, 128, 255, 0, 0, 0, 0] } } },
+                ] } },
+            ],
+        });
+        let document = Document::from_json(&json.to_string()).unwrap();
+        assert_eq!(document.version, CURRENT_VERSION);
+        let nodes = &document.history.nodes;
+        assert_eq!(nodes[0].entry.command, Command::Flattened(patch()));
+        assert_eq!(
+            nodes[1].entry.command,
+            Command::Group(vec![Command::Patch(RasterPatch {
+                cleared: vec![],
+                pasted: Some(patch()),
+            })])
+        );
+    }
+
+    #[test]
+    fn pixels_that_are_not_bytes_fail_to_migrate() {
+        let json = json!({
+            "version": 4,
+            "canvas": { "width": 4.0, "height": 4.0 },
+            "layers": [],
+            "undo_commands": [
+                { "layer": 0, "command": { "Flattened": { "x": 0, "y": 0, "width": 1, "pixels": [256, 0, 0, 0] } } },
+            ],
+            "redo_commands": [],
+        });
+        assert!(matches!(
+            Document::from_json(&json.to_string()),
+            Err(DocumentError::Migration { from: 4, .. })
+        ));
+    }
+}
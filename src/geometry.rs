@@ -0,0 +1,485 @@
+// geometry.rs
+//
+// Hit testing of commands against a point, for tools that pick up whole
+// strokes and shapes instead of pixels. A stroke is touched anywhere within
+// half its width of the line it follows, so wide strokes are easier to hit,
+// and filled shapes are touched anywhere inside as well. Distances are
+// measured on the vector data, not on the canvas, so strokes hidden under
+// later ones can still be picked.
+// Also the bounding boxes, handles and transformations of the selection tool.
+
+use crate::drawing::{Command, FillMode, Polygon, Shape, Transformation};
+use crate::layers::{live_commands, LayerCommand, LayerId};
+use dioxus::prelude::*;
+use dioxus_elements::geometry::ElementPoint;
+
+/// How close the mouse has to be to a selection handle to grab it, in screen
+/// pixels, so the handles stay the same size at any zoom.
+pub const HANDLE_RADIUS: f64 = 5.0;
+/// How far above the selection box the rotation handle sits, on screen.
+const ROTATION_HANDLE_OFFSET: f64 = 20.0;
+
+/// An axis-aligned box as its smallest and largest corners.
+pub type Bounds = (ElementPoint, ElementPoint);
+
+/// What a drag with the selection tool does, decided when the mouse goes down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectionDrag {
+    /// Rubber-band selection of everything inside the dragged box.
+    Band,
+    Move,
+    /// Uniform scaling from a corner handle, keeping the opposite corner in place.
+    Scale {
+        anchor: ElementPoint,
+        corner: ElementPoint,
+    },
+    Rotate {
+        center: ElementPoint,
+    },
+}
+
+impl SelectionDrag {
+    /// Which handle of the selection `bounds` is at `point`, or a move when
+    /// it is inside them, with the handles shown at `zoom`.
+    pub fn grab(bounds: Bounds, point: ElementPoint, zoom: f64) -> Option<Self> {
+        let near = |handle: ElementPoint| (handle - point).length() <= HANDLE_RADIUS / zoom;
+        let corners = corner_handles(bounds);
+        if near(rotation_handle(bounds, zoom)) {
+            let (min, max) = bounds;
+            Some(Self::Rotate {
+                center: min.lerp(max, 0.5),
+            })
+        } else if let Some(i) = corners.iter().position(|corner| near(*corner)) {
+            Some(Self::Scale {
+                anchor: corners[(i + 2) % 4],
+                corner: corners[i],
+            })
+        } else if contains(Shape::Rectangle(bounds.0, bounds.1), point) {
+            Some(Self::Move)
+        } else {
+            None
+        }
+    }
+
+    /// The transformation of `targets` for a drag from `start` to `end`.
+    pub fn transformation(
+        self,
+        targets: Vec<usize>,
+        start: ElementPoint,
+        end: ElementPoint,
+    ) -> Transformation {
+        let identity = Transformation {
+            targets,
+            center: start,
+            scale: 1.0,
+            rotation: 0.0,
+            offset: euclid::vec2(0.0, 0.0),
+        };
+        match self {
+            Self::Band => identity,
+            Self::Move => Transformation {
+                offset: end - start,
+                ..identity
+            },
+            Self::Scale { anchor, corner } => Transformation {
+                center: anchor,
+                // never all the way down to nothing, which couldn't be scaled back up
+                scale: ((end - anchor).length() / (corner - anchor).length()).max(0.05),
+                ..identity
+            },
+            Self::Rotate { center } => Transformation {
+                center,
+                rotation: (end - center).angle_from_x_axis().radians
+                    - (start - center).angle_from_x_axis().radians,
+                ..identity
+            },
+        }
+    }
+}
+
+/// The topmost command still drawn on `layer` that a brush of `radius` at
+/// `point` touches, as its position among the layer's entries.
+pub fn command_at(
+    history: &[LayerCommand],
+    layer: LayerId,
+    point: ElementPoint,
+    radius: f64,
+) -> Option<usize> {
+    live_commands(history, layer)
+        .into_iter()
+        .rev()
+        .find(|(_, command)| hits(command, point, radius))
+        .map(|(index, _)| index)
+}
+
+/// Whether a brush of `radius` at `point` touches what `command` drew.
+/// Flood fills and erasing have no outline to touch, so they never hit.
+pub fn hits(command: &Command, point: ElementPoint, radius: f64) -> bool {
+    match command {
+        Command::DrawPath(path) => {
+            distance_to_polyline(point, &path.points) <= radius + path.line_width / 2.0
+        }
+        Command::DrawLine(shape_properties) => {
+            let (start, end) = (shape_properties.start_point, shape_properties.end_point);
+            distance_to_segment(point, start, end) <= radius + shape_properties.line_width / 2.0
+        }
+        Command::DrawCircle(shape_properties) => hits_shape(
+            Shape::circle(shape_properties.start_point, shape_properties.end_point),
+            shape_properties.line_width,
+            shape_properties.fill_mode,
+            point,
+            radius,
+        ),
+        Command::DrawRectangle(shape_properties) => hits_shape(
+            Shape::Rectangle(shape_properties.start_point, shape_properties.end_point),
+            shape_properties.line_width,
+            shape_properties.fill_mode,
+            point,
+            radius,
+        ),
+        Command::DrawPolygon(polygon) => hits_shape(
+            Shape::Polygon(&polygon.points),
+            polygon.line_width,
+            polygon.fill_mode,
+            point,
+            radius,
+        ),
+        Command::FloodFill(_)
+        | Command::ErasePath(_)
+        | Command::EraseCanvas
+        | Command::Delete(_)
+        | Command::Transform(_)
+        | Command::Patch(_)
+        | Command::Flattened(_) => false,
+        Command::Group(commands) => commands.iter().any(|command| hits(command, point, radius)),
+    }
+}
+
+/// Distance from `point` to the closest point of the segment from `start` to `end`.
+pub fn distance_to_segment(point: ElementPoint, start: ElementPoint, end: ElementPoint) -> f64 {
+    let direction = end - start;
+    let length_squared = direction.square_length();
+    if length_squared == 0.0 {
+        return (point - start).length();
+    }
+    let along = ((point - start).dot(direction) / length_squared).clamp(0.0, 1.0);
+    (point - (start + direction * along)).length()
+}
+
+/// A single point counts as a dot, and no points as nothing at all.
+pub fn distance_to_polyline(point: ElementPoint, points: &[ElementPoint]) -> f64 {
+    match points {
+        [] => f64::INFINITY,
+        [only] => (point - *only).length(),
+        _ => points
+            .windows(2)
+            .map(|segment| distance_to_segment(point, segment[0], segment[1]))
+            .fold(f64::INFINITY, f64::min),
+    }
+}
+
+/// Distance from `point` to the outline of `shape`, whether it is inside or out.
+pub fn distance_to_outline(point: ElementPoint, shape: Shape) -> f64 {
+    match shape {
+        Shape::Polyline(points) => distance_to_polyline(point, points),
+        Shape::Polygon(points) => match points.first() {
+            Some(first) => distance_to_polyline(point, &[points, &[*first]].concat()),
+            None => f64::INFINITY,
+        },
+        Shape::Line(start, end) => distance_to_segment(point, start, end),
+        Shape::Rectangle(start, end) => {
+            let corners = [
+                start,
+                ElementPoint::new(end.x, start.y),
+                end,
+                ElementPoint::new(start.x, end.y),
+                start,
+            ];
+            distance_to_polyline(point, &corners)
+        }
+        Shape::Circle(center, radius) => ((point - center).length() - radius).abs(),
+    }
+}
+
+/// Whether `point` is inside the area `shape` fills.
+pub fn contains(shape: Shape, point: ElementPoint) -> bool {
+    match shape {
+        Shape::Polyline(points) | Shape::Polygon(points) => winding_number(points, point) != 0,
+        Shape::Line(_, _) => false,
+        Shape::Rectangle(start, end) => {
+            (start.x.min(end.x)..=start.x.max(end.x)).contains(&point.x)
+                && (start.y.min(end.y)..=start.y.max(end.y)).contains(&point.y)
+        }
+        Shape::Circle(center, radius) => (point - center).length() <= radius,
+    }
+}
+
+/// Nonzero winding of the closed polygon `points` around `p`, the canvas default fill rule.
+pub fn winding_number(points: &[ElementPoint], p: ElementPoint) -> i32 {
+    let edges = points.iter().zip(points.iter().cycle().skip(1));
+    edges.fold(0, |winding, (a, b)| {
+        let side = (*b - *a).cross(p - *a);
+        if a.y <= p.y && b.y > p.y && side > 0.0 {
+            winding + 1
+        } else if a.y > p.y && b.y <= p.y && side < 0.0 {
+            winding - 1
+        } else {
+            winding
+        }
+    })
+}
+
+/// `command` moved, scaled and rotated by `transformation`. Line widths scale
+/// along, and a rectangle that is turned becomes a polygon.
+pub fn transform_command(command: &Command, transformation: &Transformation) -> Command {
+    let point = |point: ElementPoint| transformation.apply(point);
+    let points = |points: &[ElementPoint]| points.iter().map(|p| point(*p)).collect();
+    match command {
+        Command::DrawPath(path) | Command::ErasePath(path) => {
+            let mut path = path.clone();
+            path.points = points(&path.points);
+            path.line_width *= transformation.scale;
+            match command {
+                Command::ErasePath(_) => Command::ErasePath(path),
+                _ => Command::DrawPath(path),
+            }
+        }
+        Command::DrawRectangle(shape_properties) if transformation.rotation != 0.0 => {
+            let (start, end) = (shape_properties.start_point, shape_properties.end_point);
+            let corners = [
+                start,
+                ElementPoint::new(end.x, start.y),
+                end,
+                ElementPoint::new(start.x, end.y),
+            ];
+            Command::DrawPolygon(Polygon {
+                points: points(&corners),
+                color: shape_properties.color,
+                line_width: shape_properties.line_width * transformation.scale,
+                fill_color: shape_properties.fill_color,
+                fill_mode: shape_properties.fill_mode,
+            })
+        }
+        Command::DrawLine(shape_properties)
+        | Command::DrawCircle(shape_properties)
+        | Command::DrawRectangle(shape_properties) => {
+            let mut shape_properties = shape_properties.clone();
+            shape_properties.start_point = point(shape_properties.start_point);
+            shape_properties.end_point = point(shape_properties.end_point);
+            shape_properties.line_width *= transformation.scale;
+            match command {
+                Command::DrawLine(_) => Command::DrawLine(shape_properties),
+                Command::DrawCircle(_) => Command::DrawCircle(shape_properties),
+                _ => Command::DrawRectangle(shape_properties),
+            }
+        }
+        Command::DrawPolygon(polygon) => {
+            let mut polygon = polygon.clone();
+            polygon.points = points(&polygon.points);
+            polygon.line_width *= transformation.scale;
+            Command::DrawPolygon(polygon)
+        }
+        Command::FloodFill(flood_fill) => {
+            let mut flood_fill = flood_fill.clone();
+            flood_fill.point = point(flood_fill.point);
+            Command::FloodFill(flood_fill)
+        }
+        // raster patches can't be turned, and aren't selectable to begin with
+        Command::EraseCanvas
+        | Command::Delete(_)
+        | Command::Transform(_)
+        | Command::Patch(_)
+        | Command::Flattened(_) => command.clone(),
+        Command::Group(commands) => Command::Group(
+            commands
+                .iter()
+                .map(|command| transform_command(command, transformation))
+                .collect(),
+        ),
+    }
+}
+
+/// The box around everything `command` paints, strokes included. Only the
+/// commands that can be selected have one.
+pub fn bounds(command: &Command) -> Option<Bounds> {
+    let widen = |(min, max): Bounds, line_width: f64| {
+        let half = euclid::vec2(line_width / 2.0, line_width / 2.0);
+        (min - half, max + half)
+    };
+    match command {
+        Command::DrawPath(path) => Some(widen(points_bounds(&path.points)?, path.line_width)),
+        Command::DrawLine(shape_properties) | Command::DrawRectangle(shape_properties) => {
+            let (start, end) = (shape_properties.start_point, shape_properties.end_point);
+            Some(widen(
+                (start.min(end), start.max(end)),
+                shape_properties.line_width,
+            ))
+        }
+        Command::DrawCircle(shape_properties) => {
+            let center = shape_properties.start_point;
+            let radius = (shape_properties.end_point - center).length();
+            let extent = euclid::vec2(radius, radius);
+            Some(widen(
+                (center - extent, center + extent),
+                shape_properties.line_width,
+            ))
+        }
+        Command::DrawPolygon(polygon) => {
+            Some(widen(points_bounds(&polygon.points)?, polygon.line_width))
+        }
+        Command::FloodFill(_)
+        | Command::ErasePath(_)
+        | Command::EraseCanvas
+        | Command::Delete(_)
+        | Command::Transform(_)
+        | Command::Patch(_)
+        | Command::Flattened(_) => None,
+        Command::Group(commands) => union_bounds(commands),
+    }
+}
+
+/// The live commands among `selection` on `layer`, with their positions.
+pub fn selected_commands(
+    history: &[LayerCommand],
+    layer: LayerId,
+    selection: &[usize],
+) -> Vec<(usize, Command)> {
+    live_commands(history, layer)
+        .into_iter()
+        .filter(|(index, _)| selection.contains(index))
+        .collect()
+}
+
+/// The box around all of `commands`, if any of them has one.
+pub fn union_bounds<'a>(commands: impl IntoIterator<Item = &'a Command>) -> Option<Bounds> {
+    commands
+        .into_iter()
+        .filter_map(bounds)
+        .reduce(|(min, max), (other_min, other_max)| (min.min(other_min), max.max(other_max)))
+}
+
+/// The live commands on `layer` that lie entirely inside `band`.
+pub fn commands_within(history: &[LayerCommand], layer: LayerId, band: Bounds) -> Vec<usize> {
+    let (band_min, band_max) = (band.0.min(band.1), band.0.max(band.1));
+    live_commands(history, layer)
+        .into_iter()
+        .filter(|(_, command)| {
+            bounds(command).is_some_and(|(min, max)| {
+                min.x >= band_min.x
+                    && min.y >= band_min.y
+                    && max.x <= band_max.x
+                    && max.y <= band_max.y
+            })
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// The scaling handles, clockwise from the top left corner, so opposite
+/// corners are two apart.
+pub fn corner_handles((min, max): Bounds) -> [ElementPoint; 4] {
+    [
+        min,
+        ElementPoint::new(max.x, min.y),
+        max,
+        ElementPoint::new(min.x, max.y),
+    ]
+}
+
+/// Above the middle of the top edge, as far on screen at any `zoom`.
+pub fn rotation_handle((min, max): Bounds, zoom: f64) -> ElementPoint {
+    ElementPoint::new((min.x + max.x) / 2.0, min.y - ROTATION_HANDLE_OFFSET / zoom)
+}
+
+pub fn points_bounds(points: &[ElementPoint]) -> Option<Bounds> {
+    let first = *points.first()?;
+    Some(points.iter().fold((first, first), |(min, max), point| {
+        (min.min(*point), max.max(*point))
+    }))
+}
+
+fn hits_shape(
+    shape: Shape,
+    line_width: f64,
+    fill_mode: FillMode,
+    point: ElementPoint,
+    radius: f64,
+) -> bool {
+    (fill_mode.strokes() && distance_to_outline(point, shape) <= radius + line_width / 2.0)
+        || (fill_mode.fills()
+            && (contains(shape, point) || distance_to_outline(point, shape) <= radius))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drawing::{Color, FillMode, ShapeProperties};
+
+    fn line(y: f64) -> LayerCommand {
+        LayerCommand {
+            layer: 0,
+            command: Command::DrawLine(ShapeProperties {
+                start_point: ElementPoint::new(0.0, y),
+                end_point: ElementPoint::new(10.0, y),
+                color: Color(0.0, 0.0, 0.0, 1.0),
+                line_width: 2.0,
+                fill_color: Color(255.0, 255.0, 255.0, 1.0),
+                fill_mode: FillMode::Stroke,
+            }),
+        }
+    }
+
+    fn clear() -> LayerCommand {
+        LayerCommand {
+            layer: 0,
+            command: Command::EraseCanvas,
+        }
+    }
+
+    #[test]
+    fn cleared_commands_cannot_be_hit() {
+        let history = [line(5.0), clear()];
+        assert_eq!(
+            command_at(&history[..1], 0, ElementPoint::new(5.0, 5.0), 0.0),
+            Some(0)
+        );
+        assert_eq!(
+            command_at(&history, 0, ElementPoint::new(5.0, 5.0), 0.0),
+            None
+        );
+
+        let history = [line(5.0), clear(), line(5.0)];
+        assert_eq!(
+            command_at(&history, 0, ElementPoint::new(5.0, 5.0), 0.0),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn cleared_commands_cannot_be_selected() {
+        let band = (ElementPoint::new(-1.0, -1.0), ElementPoint::new(20.0, 20.0));
+        let history = [line(5.0), line(8.0), clear(), line(3.0)];
+        assert_eq!(commands_within(&history, 0, band), [3]);
+        assert!(selected_commands(&history, 0, &[0, 1]).is_empty());
+    }
+
+    #[test]
+    fn handles_are_the_same_size_on_screen_at_any_zoom() {
+        let bounds = (ElementPoint::new(10.0, 10.0), ElementPoint::new(50.0, 30.0));
+        // 4 document pixels off the top left corner is 8 on screen at zoom 2
+        let off_corner = ElementPoint::new(6.0, 10.0);
+        assert!(matches!(
+            SelectionDrag::grab(bounds, off_corner, 1.0),
+            Some(SelectionDrag::Scale { .. })
+        ));
+        assert_eq!(SelectionDrag::grab(bounds, off_corner, 2.0), None);
+
+        // and the rotation handle stays as far above the box on screen
+        assert_eq!(rotation_handle(bounds, 1.0), ElementPoint::new(30.0, -10.0));
+        assert_eq!(rotation_handle(bounds, 4.0), ElementPoint::new(30.0, 5.0));
+        assert!(matches!(
+            SelectionDrag::grab(bounds, ElementPoint::new(31.0, 5.0), 4.0),
+            Some(SelectionDrag::Rotate { .. })
+        ));
+    }
+}
@@ -0,0 +1,1149 @@
+// history.rs
+//
+// The undo history, kept as a tree so drawing after an undo starts a new
+// branch instead of throwing the undone work away. Every entry is a node
+// whose parent is the state it was drawn on. The path from the root to the
+// current node is what's on the canvas. Its entries are kept in order in
+// `undo`, and the entries of all other nodes stay in the nodes themselves,
+// so moving around the tree moves entries rather than copying them.
+//
+// Redrawing a layer used to replay every command ever drawn on it, so undo
+// slowed down the longer a session ran. Replays now leave raster snapshots
+// of the layer behind every few entries, and the next redraw starts from the
+// latest usable one and only replays the rest. A snapshot belongs to the node
+// it was taken at, so it stays usable while that node is on the current path,
+// unless a later deletion or transformation changes a command it shows.
+//
+// Once the history outgrows its budget, the oldest part of the current path
+// is flattened into one image per layer, which replaces those entries and
+// the branches that leave from them.
+
+use crate::drawing::{Command, PixelPatch, RenderTarget};
+use crate::layers::{layer_commands, live_commands, Layer, LayerCommand, LayerId};
+use dioxus::prelude::*;
+use dioxus_elements::geometry::ElementPoint;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem::size_of;
+
+/// How many of a layer's entries apart its checkpoints are.
+pub const CHECKPOINT_INTERVAL: usize = 20;
+/// Snapshots are as big as the canvas, so only the latest few per layer are kept.
+pub const MAX_CHECKPOINTS: usize = 8;
+/// How many milliseconds apart coalesced entries can be and still join.
+pub const COALESCE_WINDOW: f64 = 1000.0;
+
+/// Where the history budget is kept in local storage.
+pub const BUDGET_STORAGE_KEY: &str = "history_budget";
+
+/// How much history to keep before the oldest of it is flattened. Going over
+/// either limit flattens enough to get back under three quarters of both.
+/// The flattened images, at most one per layer, don't count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryBudget {
+    pub max_entries: usize,
+    /// Roughly, see `History::estimated_bytes`.
+    pub max_bytes: usize,
+}
+
+impl Default for HistoryBudget {
+    fn default() -> Self {
+        Self {
+            max_entries: 1000,
+            max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// A node of the history tree. Ids count up in the order entries were
+/// recorded, and `None` stands for the blank drawing at the root.
+pub type NodeId = usize;
+
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    nodes: Vec<Node>,
+    /// The nodes from the root to the current one.
+    path: Vec<NodeId>,
+    /// The entries of `path`, in the same order.
+    undo: Vec<LayerCommand>,
+    /// Where redo goes from the root.
+    root_redo: Option<NodeId>,
+    /// The node the last coalesced entry went into, what kind it was and when.
+    coalescing: Option<(NodeId, &'static str, f64)>,
+    /// Where the open transaction started.
+    transaction: Option<Option<NodeId>>,
+    // caches filled in while drawing, so they can change behind a shared reference
+    checkpoints: RefCell<Vec<Checkpoint>>,
+    thumbnails: RefCell<HashMap<Option<NodeId>, (LayersShown, String)>>,
+}
+
+/// The visible layers, bottom to top, with their opacity bits: what a
+/// thumbnail shows besides the history.
+type LayersShown = Vec<(LayerId, u64)>;
+
+#[derive(Clone, Debug)]
+struct Node {
+    parent: Option<NodeId>,
+    /// The child redo goes to: the one last visited.
+    redo: Option<NodeId>,
+    /// How many entries come before it on its path.
+    depth: usize,
+    layer: LayerId,
+    /// Empty while the node is on the current path, which keeps it in `undo`.
+    entry: Option<LayerCommand>,
+}
+
+/// The history tree as it's saved in a file: every node in the order it was
+/// recorded, which puts parents before their children.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedHistory {
+    pub nodes: Vec<SavedNode>,
+    /// Where redo goes from the root.
+    pub root_redo: Option<NodeId>,
+    pub current: Option<NodeId>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedNode {
+    pub parent: Option<NodeId>,
+    /// The child redo goes to.
+    pub redo: Option<NodeId>,
+    pub entry: LayerCommand,
+}
+
+impl SavedHistory {
+    /// Whether the nodes make a tree `History::from_saved` can take.
+    pub fn check(&self) -> Result<(), String> {
+        let count = self.nodes.len();
+        let redo_fits = |parent: Option<NodeId>, redo: Option<NodeId>| {
+            redo.is_none_or(|child| child < count && self.nodes[child].parent == parent)
+        };
+        for (node, saved) in self.nodes.iter().enumerate() {
+            if saved.parent.is_some_and(|parent| parent >= node) {
+                return Err(format!("node {} comes before its parent", node));
+            }
+            if !redo_fits(Some(node), saved.redo) {
+                return Err(format!(
+                    "node {} redoes to a node that isn't its child",
+                    node
+                ));
+            }
+        }
+        if !redo_fits(None, self.root_redo) {
+            return Err("the root redoes to a node that isn't its child".to_string());
+        }
+        if self.current.is_some_and(|node| node >= count) {
+            return Err("the current node isn't in the history".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A layer as it looked right after `node`, its `entries`th entry, was drawn.
+#[derive(Clone, Debug)]
+struct Checkpoint {
+    layer: LayerId,
+    node: NodeId,
+    entries: usize,
+    pixels: PixelPatch,
+}
+
+/// How a node relates to where the history is now.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeState {
+    Current,
+    /// On the canvas, undo goes back through it.
+    Done,
+    /// Redo gets back to it.
+    Undone,
+    /// On another branch, only reachable by jumping to it.
+    Abandoned,
+}
+
+impl History {
+    /// The history `saved` describes, which has to have passed `check`.
+    pub fn from_saved(saved: SavedHistory) -> Self {
+        let mut history = Self {
+            root_redo: saved.root_redo,
+            ..Self::default()
+        };
+        for saved_node in saved.nodes {
+            let depth = saved_node
+                .parent
+                .map_or(0, |parent| history.nodes[parent].depth + 1);
+            history.nodes.push(Node {
+                parent: saved_node.parent,
+                redo: saved_node.redo,
+                depth,
+                layer: saved_node.entry.layer,
+                entry: Some(saved_node.entry),
+            });
+        }
+        history.jump(saved.current);
+        history
+    }
+
+    /// The whole tree, for saving, abandoned branches included.
+    pub fn saved(&self) -> SavedHistory {
+        SavedHistory {
+            nodes: (0..self.nodes.len())
+                .map(|node| SavedNode {
+                    parent: self.nodes[node].parent,
+                    redo: self.nodes[node].redo,
+                    entry: self.entry(node).clone(),
+                })
+                .collect(),
+            root_redo: self.root_redo,
+            current: self.current(),
+        }
+    }
+
+    /// Everything drawn, oldest first.
+    pub fn undo_commands(&self) -> &[LayerCommand] {
+        &self.undo
+    }
+
+    pub fn current(&self) -> Option<NodeId> {
+        self.path.last().copied()
+    }
+
+    /// Adds an entry after the current one. Whatever could be redone stays
+    /// in the tree, on a branch of its own.
+    pub fn record(&mut self, entry: LayerCommand) {
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            parent: self.current(),
+            redo: None,
+            depth: self.path.len(),
+            layer: entry.layer,
+            entry: None,
+        });
+        self.set_redo_child(self.current(), node);
+        self.path.push(node);
+        self.undo.push(entry);
+        self.coalescing = None;
+    }
+
+    /// Records `entry`, or when the current entry was recorded this way too,
+    /// with the same `kind` on the same layer less than `COALESCE_WINDOW`
+    /// before `time`, adds it to that entry so the whole run undoes at once.
+    pub fn record_coalesced(&mut self, entry: LayerCommand, kind: &'static str, time: f64) {
+        let joins = match (self.coalescing, self.current()) {
+            (Some((node, last_kind, last_time)), Some(current)) => {
+                node == current
+                    && last_kind == kind
+                    && time - last_time < COALESCE_WINDOW
+                    && self.nodes[node].layer == entry.layer
+            }
+            _ => false,
+        };
+        if joins {
+            let node = self.current().unwrap();
+            let last = self.undo.last_mut().unwrap();
+            last.command = Command::group(vec![last.command.clone(), entry.command]);
+            self.forget_caches_from(node);
+        } else {
+            self.record(entry);
+        }
+        self.coalescing = self.current().map(|node| (node, kind, time));
+    }
+
+    /// Starts a transaction: what's recorded until `end_transaction` undoes in
+    /// one step. The history isn't compacted in between.
+    pub fn begin_transaction(&mut self) {
+        self.transaction = Some(self.current());
+    }
+
+    pub fn end_transaction(&mut self) {
+        if let Some(mark) = self.transaction.take() {
+            self.group_since(mark);
+        }
+    }
+
+    /// Turns everything recorded on top of `mark`, an earlier `current()`, into
+    /// one entry so it undoes in one step. Only entries recorded one after
+    /// the other on a single layer can be joined, anything else stays as it is.
+    pub fn group_since(&mut self, mark: Option<NodeId>) {
+        let start = mark.map_or(0, |node| self.nodes[node].depth + 1);
+        let grouped = match self.path.get(start..) {
+            Some(grouped) if grouped.len() > 1 => grouped,
+            _ => return,
+        };
+        let first = grouped[0];
+        let layer = self.nodes[first].layer;
+        // straight after one another means the newest nodes, one after the other
+        let recorded_in_order = grouped
+            .iter()
+            .enumerate()
+            .all(|(i, node)| *node == first + i && self.nodes[*node].layer == layer);
+        let newest = first + grouped.len() == self.nodes.len();
+        let on_path = mark.is_none_or(|node| self.path[start - 1] == node);
+        if !(on_path && recorded_in_order && newest) {
+            return;
+        }
+
+        self.nodes.truncate(first);
+        self.path.truncate(start);
+        let commands = self
+            .undo
+            .split_off(start)
+            .into_iter()
+            .map(|entry| entry.command)
+            .collect();
+        self.forget_caches_from(first);
+        self.record(LayerCommand {
+            layer,
+            command: Command::group(commands),
+        });
+    }
+
+    /// Steps back to the parent of the current node and returns the entry undone.
+    pub fn undo(&mut self) -> Option<&LayerCommand> {
+        let node = self.path.pop()?;
+        self.nodes[node].entry = self.undo.pop();
+        self.coalescing = None;
+        Some(self.entry(node))
+    }
+
+    /// Steps forward to the child last visited and returns the entry redone.
+    pub fn redo(&mut self) -> Option<&LayerCommand> {
+        let node = self.redo_child()?;
+        self.undo.extend(self.nodes[node].entry.take());
+        self.path.push(node);
+        self.undo.last()
+    }
+
+    /// Makes `target` the current node and returns the layers that changed on
+    /// the way, which need to be redrawn.
+    pub fn jump(&mut self, target: Option<NodeId>) -> Vec<LayerId> {
+        let target_path = self.path_to(target);
+        let shared = self
+            .path
+            .iter()
+            .zip(&target_path)
+            .take_while(|(node, other)| node == other)
+            .count();
+
+        let mut changed: Vec<LayerId> = vec![];
+        self.path[shared..]
+            .iter()
+            .chain(&target_path[shared..])
+            .for_each(|node| {
+                if !changed.contains(&self.nodes[*node].layer) {
+                    changed.push(self.nodes[*node].layer);
+                }
+            });
+
+        while self.path.len() > shared {
+            self.undo();
+        }
+        target_path[shared..].iter().for_each(|node| {
+            self.set_redo_child(self.nodes[*node].parent, *node);
+            self.redo();
+        });
+        changed
+    }
+
+    /// Every node, in the order the history panel lists them: each branch
+    /// right after the node it branches off, with how many branches deep it
+    /// is. The root isn't included.
+    pub fn nodes(&self) -> Vec<(NodeId, usize)> {
+        let mut children: HashMap<Option<NodeId>, Vec<NodeId>> = HashMap::new();
+        (0..self.nodes.len()).for_each(|node| {
+            children
+                .entry(self.nodes[node].parent)
+                .or_default()
+                .push(node)
+        });
+
+        // the first child continues its parent's branch, the others start their own
+        let push_children = |pending: &mut Vec<(NodeId, usize)>, parent, level| {
+            if let Some(nodes) = children.get(&parent) {
+                nodes
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .for_each(|(i, child)| pending.push((*child, level + i)));
+            }
+        };
+        let mut listed = vec![];
+        let mut pending = vec![];
+        push_children(&mut pending, None, 0);
+        while let Some((node, level)) = pending.pop() {
+            listed.push((node, level));
+            push_children(&mut pending, Some(node), level);
+        }
+        listed
+    }
+
+    pub fn entry(&self, node: NodeId) -> &LayerCommand {
+        match &self.nodes[node].entry {
+            Some(entry) => entry,
+            None => &self.undo[self.nodes[node].depth],
+        }
+    }
+
+    pub fn node_state(&self, node: NodeId) -> NodeState {
+        let Node { depth, .. } = self.nodes[node];
+        if self.path.get(depth) == Some(&node) {
+            match self.current() == Some(node) {
+                true => NodeState::Current,
+                false => NodeState::Done,
+            }
+        } else if self.redo_path().contains(&node) {
+            NodeState::Undone
+        } else {
+            NodeState::Abandoned
+        }
+    }
+
+    /// The entries on the way from the root to `node`.
+    pub fn entries_to(&self, node: Option<NodeId>) -> Vec<LayerCommand> {
+        self.path_to(node)
+            .into_iter()
+            .map(|node| self.entry(node).clone())
+            .collect()
+    }
+
+    /// A picture of the drawing at `node` with `layers` as they are, made by
+    /// `draw` the first time it's asked for and again once the layers are
+    /// shown differently.
+    pub fn thumbnail(
+        &self,
+        node: Option<NodeId>,
+        layers: &[Layer],
+        draw: impl FnOnce() -> String,
+    ) -> String {
+        let shown: LayersShown = layers
+            .iter()
+            .filter(|layer| layer.visible)
+            .map(|layer| (layer.id, layer.opacity.to_bits()))
+            .collect();
+        if let Some((_, thumbnail)) = self
+            .thumbnails
+            .borrow()
+            .get(&node)
+            .filter(|(cached, _)| *cached == shown)
+        {
+            return thumbnail.clone();
+        }
+        let thumbnail = draw();
+        self.thumbnails
+            .borrow_mut()
+            .insert(node, (shown, thumbnail.clone()));
+        thumbnail
+    }
+
+    /// Whether anything was ever drawn on `layer`, on any branch.
+    pub fn touches_layer(&self, layer: LayerId) -> bool {
+        self.nodes.iter().any(|node| node.layer == layer)
+    }
+
+    /// Forgets everything drawn on `layer`, on every branch. What was drawn
+    /// on other layers after it moves up to take its place.
+    pub fn remove_layer(&mut self, layer: LayerId) {
+        let current = self.current();
+        // with every entry back in its node, the tree can be rebuilt without them
+        self.jump(None);
+        let old_nodes = std::mem::take(&mut self.nodes);
+        let removed: Vec<bool> = old_nodes.iter().map(|node| node.layer == layer).collect();
+        let old_redo: Vec<Option<NodeId>> = old_nodes.iter().map(|node| node.redo).collect();
+
+        // where each node went, or for a removed one, its closest kept ancestor
+        let mut moved: Vec<Option<NodeId>> = vec![];
+        for (old, node) in old_nodes.into_iter().enumerate() {
+            // nodes come after their parents, so those have moved already
+            let parent = node.parent.and_then(|parent| moved[parent]);
+            if removed[old] {
+                moved.push(parent);
+                continue;
+            }
+            moved.push(Some(self.nodes.len()));
+            self.nodes.push(Node {
+                parent,
+                redo: None,
+                depth: parent.map_or(0, |parent| self.nodes[parent].depth + 1),
+                ..node
+            });
+        }
+        // redo skips over the removed nodes
+        let follow = |mut next: Option<NodeId>| {
+            while let Some(node) = next.filter(|node| removed[*node]) {
+                next = old_redo[node];
+            }
+            next.and_then(|node| moved[node])
+        };
+        self.root_redo = follow(self.root_redo);
+        for old in (0..removed.len()).filter(|old| !removed[*old]) {
+            let node = moved[old].unwrap();
+            self.nodes[node].redo = follow(old_redo[old]);
+        }
+
+        self.checkpoints.get_mut().clear();
+        self.thumbnails.get_mut().clear();
+        self.jump(current.and_then(|node| moved[node]));
+    }
+
+    /// Roughly how much memory the entries take, on every branch, leaving out
+    /// the flattened images.
+    pub fn estimated_bytes(&self) -> usize {
+        (0..self.nodes.len())
+            .map(|node| estimated_bytes(&self.entry(node).command))
+            .sum()
+    }
+
+    pub fn over_budget(&self, budget: HistoryBudget) -> bool {
+        let entries = (0..self.nodes.len())
+            .filter(|node| !matches!(self.entry(*node).command, Command::Flattened(_)))
+            .count();
+        entries > budget.max_entries || self.estimated_bytes() > budget.max_bytes
+    }
+
+    /// Once the history is over `budget`, flattens the oldest entries on the
+    /// current path into one image per layer and drops the branches leaving
+    /// from them. The images are drawn on `scratch`, which has to be the size
+    /// of the drawing; the flattened layers are left for the caller to redraw.
+    ///
+    /// Returns each flattened layer with how many of its entries became the
+    /// image. Later positions on the layer move back by one less than that.
+    pub fn compact<T: RenderTarget>(
+        &mut self,
+        budget: HistoryBudget,
+        scratch: &mut T,
+    ) -> Vec<(LayerId, usize)> {
+        // positions would shift under a transaction's feet
+        if self.transaction.is_some() || !self.over_budget(budget) {
+            return vec![];
+        }
+        let Some(flattened) = self.flattened_length(budget) else {
+            return vec![];
+        };
+        let last = self.path[flattened - 1];
+        let mut layers: Vec<(LayerId, usize)> = vec![];
+        self.undo[..flattened].iter().for_each(|entry| {
+            match layers.iter_mut().find(|(layer, _)| *layer == entry.layer) {
+                Some((_, count)) => *count += 1,
+                None => layers.push((entry.layer, 1)),
+            }
+        });
+        let images: Vec<PixelPatch> = layers
+            .iter()
+            .map(|(layer, _)| {
+                scratch.clear();
+                scratch.execute_commands(&layer_commands(&self.undo[..flattened], *layer));
+                scratch.snapshot()
+            })
+            .collect();
+
+        let current = self.current();
+        // with every entry back in its node, the tree can be rebuilt
+        self.jump(None);
+        let old_nodes = std::mem::take(&mut self.nodes);
+        let kept = descendants(&old_nodes, last);
+        let old_redo: Vec<Option<NodeId>> = old_nodes.iter().map(|node| node.redo).collect();
+
+        // the images go first, one after the other, and the rest hangs off the last
+        for (i, ((layer, _), pixels)) in layers.iter().zip(images).enumerate() {
+            self.nodes.push(Node {
+                parent: i.checked_sub(1),
+                redo: Some(i + 1),
+                depth: i,
+                layer: *layer,
+                entry: Some(LayerCommand {
+                    layer: *layer,
+                    command: Command::Flattened(pixels),
+                }),
+            });
+        }
+        let mut moved: Vec<Option<NodeId>> = vec![None; old_nodes.len()];
+        moved[last] = Some(self.nodes.len() - 1);
+        for (old, node) in old_nodes.into_iter().enumerate() {
+            if !kept[old] {
+                continue;
+            }
+            let parent = node.parent.and_then(|parent| moved[parent]);
+            let mut entry = node.entry.unwrap();
+            if let Some((_, count)) = layers.iter().find(|(layer, _)| *layer == entry.layer) {
+                renumber(&mut entry.command, *count);
+            }
+            moved[old] = Some(self.nodes.len());
+            self.nodes.push(Node {
+                parent,
+                redo: None,
+                depth: parent.map_or(0, |parent| self.nodes[parent].depth + 1),
+                entry: Some(entry),
+                ..node
+            });
+        }
+        let map_redo = |old: NodeId| old_redo[old].and_then(|node| moved[node]);
+        let last_image = layers.len() - 1;
+        self.nodes[last_image].redo = map_redo(last);
+        (0..moved.len())
+            .filter(|old| kept[*old])
+            .for_each(|old| self.nodes[moved[old].unwrap()].redo = map_redo(old));
+        self.root_redo = Some(0);
+
+        self.coalescing = None;
+        self.checkpoints.get_mut().clear();
+        self.thumbnails.get_mut().clear();
+        self.jump(current.and_then(|node| moved[node]));
+        layers
+    }
+
+    /// Drops the checkpoints, which only fit targets the size of the ones
+    /// they were taken on.
+    pub fn forget_checkpoints(&self) {
+        self.checkpoints.borrow_mut().clear();
+    }
+
+    /// Clears `target` and draws everything on `layer` onto it, starting from
+    /// the latest usable checkpoint and leaving new ones behind.
+    pub fn render_layer<T: RenderTarget>(&self, target: &mut T, layer: LayerId) {
+        self.render_path(target, layer, &self.path, &self.undo, true);
+    }
+
+    /// Clears `target` and draws `layer` as it was at `node` onto it,
+    /// starting from the latest checkpoint on the way there. Takes no new
+    /// checkpoints, which would push out the ones of the current path.
+    pub fn render_layer_at<T: RenderTarget>(
+        &self,
+        target: &mut T,
+        layer: LayerId,
+        node: Option<NodeId>,
+    ) {
+        self.render_path(
+            target,
+            layer,
+            &self.path_to(node),
+            &self.entries_to(node),
+            false,
+        );
+    }
+
+    // PRIVATE
+
+    /// Renders `layer` after the nodes of `path`, whose entries are `history`.
+    fn render_path<T: RenderTarget>(
+        &self,
+        target: &mut T,
+        layer: LayerId,
+        path: &[NodeId],
+        history: &[LayerCommand],
+        take_checkpoints: bool,
+    ) {
+        let nodes: Vec<NodeId> = path
+            .iter()
+            .copied()
+            .filter(|node| self.nodes[*node].layer == layer)
+            .collect();
+        let entries: Vec<&Command> = nodes
+            .iter()
+            .map(|node| &self.entry(*node).command)
+            .collect();
+        let start = match self.latest_checkpoint(layer, &nodes, &entries) {
+            Some(checkpoint) => {
+                target.restore(&checkpoint.pixels);
+                checkpoint.entries
+            }
+            None => {
+                target.clear();
+                0
+            }
+        };
+
+        // only the latest checkpoints are kept, so don't bother taking older ones
+        let oldest_kept = entries
+            .len()
+            .saturating_sub(CHECKPOINT_INTERVAL * MAX_CHECKPOINTS);
+        let mut next = (start / CHECKPOINT_INTERVAL + 1) * CHECKPOINT_INTERVAL;
+        let mut pass_checkpoints = |target: &mut T, up_to: usize| {
+            while take_checkpoints && next <= up_to {
+                if next >= oldest_kept && !reaches_back(&entries, next) {
+                    self.add_checkpoint(layer, nodes[next - 1], next, target.snapshot());
+                }
+                next += CHECKPOINT_INTERVAL;
+            }
+        };
+        live_commands(history, layer)
+            .into_iter()
+            .filter(|(index, _)| *index >= start)
+            .for_each(|(index, command)| {
+                pass_checkpoints(target, index);
+                command.execute(target);
+            });
+        pass_checkpoints(target, entries.len());
+    }
+
+    /// How many entries at the start of the current path have to be flattened
+    /// to get well under `budget`, if there's a way to.
+    fn flattened_length(&self, budget: HistoryBudget) -> Option<usize> {
+        let (goal_entries, goal_bytes) = (budget.max_entries * 3 / 4, budget.max_bytes * 3 / 4);
+        // the flattened images don't count, like in `over_budget`
+        let size = |node: NodeId| match &self.entry(node).command {
+            Command::Flattened(_) => (0, 0),
+            command => (1, estimated_bytes(command)),
+        };
+        // what's left after flattening up to a node is what comes after it
+        let mut sizes: Vec<(usize, usize)> = (0..self.nodes.len()).map(size).collect();
+        for node in (0..self.nodes.len()).rev() {
+            if let Some(parent) = self.nodes[node].parent {
+                sizes[parent].0 += sizes[node].0;
+                sizes[parent].1 += sizes[node].1;
+            }
+        }
+
+        (1..=self.path.len()).find(|length| {
+            let last = self.path[length - 1];
+            let ((entries, bytes), (own_entries, own_bytes)) = (sizes[last], size(last));
+            let (left_entries, left_bytes) = (entries - own_entries, bytes - own_bytes);
+            left_entries <= goal_entries && left_bytes <= goal_bytes && self.can_flatten(*length)
+        })
+    }
+
+    /// Whether nothing left after flattening `length` entries deletes or
+    /// transforms one of them, which would have nothing left to work on.
+    fn can_flatten(&self, length: usize) -> bool {
+        let flattened = |layer: LayerId| {
+            self.undo[..length]
+                .iter()
+                .filter(|entry| entry.layer == layer)
+                .count()
+        };
+        let kept = descendants(&self.nodes, self.path[length - 1]);
+        (0..self.nodes.len())
+            .filter(|node| kept[*node])
+            .all(|node| {
+                let LayerCommand { layer, command } = self.entry(node);
+                let targets = command.targets();
+                targets.is_empty() || {
+                    let flattened = flattened(*layer);
+                    targets.into_iter().all(|target| target >= flattened)
+                }
+            })
+    }
+
+    /// Drops the checkpoints and thumbnails of `node` and the ones recorded
+    /// after it, which no longer show what's there.
+    fn forget_caches_from(&mut self, node: NodeId) {
+        self.checkpoints
+            .get_mut()
+            .retain(|checkpoint| checkpoint.node < node);
+        self.thumbnails
+            .get_mut()
+            .retain(|thumbnail_node, _| *thumbnail_node < Some(node));
+    }
+
+    fn redo_child(&self) -> Option<NodeId> {
+        match self.current() {
+            Some(node) => self.nodes[node].redo,
+            None => self.root_redo,
+        }
+    }
+
+    fn set_redo_child(&mut self, parent: Option<NodeId>, child: NodeId) {
+        match parent {
+            Some(parent) => self.nodes[parent].redo = Some(child),
+            None => self.root_redo = Some(child),
+        }
+    }
+
+    fn redo_path(&self) -> Vec<NodeId> {
+        let mut redo = vec![];
+        let mut next = self.redo_child();
+        while let Some(node) = next {
+            redo.push(node);
+            next = self.nodes[node].redo;
+        }
+        redo
+    }
+
+    /// The nodes from the root to `node`.
+    fn path_to(&self, node: Option<NodeId>) -> Vec<NodeId> {
+        let mut path: Vec<NodeId> =
+            std::iter::successors(node, |node| self.nodes[*node].parent).collect();
+        path.reverse();
+        path
+    }
+
+    fn latest_checkpoint(
+        &self,
+        layer: LayerId,
+        nodes: &[NodeId],
+        entries: &[&Command],
+    ) -> Option<Checkpoint> {
+        self.checkpoints
+            .borrow()
+            .iter()
+            .filter(|checkpoint| checkpoint.layer == layer)
+            .filter(|checkpoint| nodes.get(checkpoint.entries - 1) == Some(&checkpoint.node))
+            .filter(|checkpoint| !reaches_back(entries, checkpoint.entries))
+            .max_by_key(|checkpoint| checkpoint.entries)
+            .cloned()
+    }
+
+    fn add_checkpoint(&self, layer: LayerId, node: NodeId, entries: usize, pixels: PixelPatch) {
+        let mut checkpoints = self.checkpoints.borrow_mut();
+        if checkpoints.iter().any(|checkpoint| checkpoint.node == node) {
+            return;
+        }
+        checkpoints.push(Checkpoint {
+            layer,
+            node,
+            entries,
+            pixels,
+        });
+        let on_layer = || {
+            checkpoints
+                .iter()
+                .filter(|checkpoint| checkpoint.layer == layer)
+        };
+        if on_layer().count() > MAX_CHECKPOINTS {
+            let oldest = on_layer().map(|checkpoint| checkpoint.node).min();
+            checkpoints.retain(|checkpoint| Some(checkpoint.node) != oldest);
+        }
+    }
+}
+
+/// Which nodes are below `node` in the tree.
+fn descendants(nodes: &[Node], node: NodeId) -> Vec<bool> {
+    let mut below = vec![false; nodes.len()];
+    // children always come after their parents
+    for child in node + 1..nodes.len() {
+        below[child] = nodes[child]
+            .parent
+            .is_some_and(|parent| parent == node || below[parent]);
+    }
+    below
+}
+
+/// Moves the positions `command` refers to back to where they are once the
+/// first `flattened` entries of the layer are one image.
+fn renumber(command: &mut Command, flattened: usize) {
+    match command {
+        Command::Delete(target) => *target -= flattened - 1,
+        Command::Transform(transformation) => transformation
+            .targets
+            .iter_mut()
+            .for_each(|target| *target -= flattened - 1),
+        Command::Group(commands) => commands
+            .iter_mut()
+            .for_each(|command| renumber(command, flattened)),
+        _ => {}
+    }
+}
+
+fn estimated_bytes(command: &Command) -> usize {
+    let points = |points: &[ElementPoint]| std::mem::size_of_val(points);
+    size_of::<LayerCommand>()
+        + match command {
+            Command::DrawPath(path) | Command::ErasePath(path) => points(&path.points),
+            Command::DrawPolygon(polygon) => points(&polygon.points),
+            Command::Transform(transformation) => {
+                std::mem::size_of_val(transformation.targets.as_slice())
+            }
+            Command::Patch(patch) => {
+                points(&patch.cleared)
+                    + patch
+                        .pasted
+                        .as_ref()
+                        .map_or(0, |pasted| pasted.pixels.len())
+            }
+            Command::Group(commands) => commands.iter().map(estimated_bytes).sum(),
+            Command::Flattened(_) => 0,
+            Command::DrawLine(_)
+            | Command::DrawCircle(_)
+            | Command::DrawRectangle(_)
+            | Command::FloodFill(_)
+            | Command::EraseCanvas
+            | Command::Delete(_) => 0,
+        }
+}
+
+/// Whether anything from `position` on deletes or transforms a command before it,
+/// which makes the layer as it stood at `position` useless to replay from.
+fn reaches_back(entries: &[&Command], position: usize) -> bool {
+    entries.iter().skip(position).any(|command| {
+        command
+            .targets()
+            .into_iter()
+            .any(|target| target < position)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drawing::{Color, FillMode, ShapeProperties, Transformation};
+    use crate::raster::Raster;
+
+    const SIZE: usize = 32;
+
+    /// A different line for every `i`, so replaying out of order shows.
+    fn line(layer: LayerId, i: usize) -> LayerCommand {
+        let y = (i * 7 % SIZE) as f64 + 0.5;
+        LayerCommand {
+            layer,
+            command: Command::DrawLine(ShapeProperties {
+                start_point: ElementPoint::new(0.0, y),
+                end_point: ElementPoint::new((i % SIZE) as f64 + 1.0, y),
+                color: Color((i * 37 % 256) as f64, 0.0, 255.0, 1.0),
+                line_width: 1.0,
+                fill_color: Color(255.0, 255.0, 255.0, 1.0),
+                fill_mode: FillMode::Stroke,
+            }),
+        }
+    }
+
+    fn lines(history: &mut History, layer: LayerId, range: std::ops::Range<usize>) {
+        range.for_each(|i| history.record(line(layer, i)));
+    }
+
+    fn moved(targets: Vec<usize>) -> LayerCommand {
+        LayerCommand {
+            layer: 0,
+            command: Command::Transform(Transformation {
+                targets,
+                center: ElementPoint::new(0.0, 0.0),
+                scale: 1.0,
+                rotation: 0.0,
+                offset: euclid::vec2(3.0, 1.0),
+            }),
+        }
+    }
+
+    /// Renders every layer through the checkpoints and checks it against
+    /// replaying all of the layer's commands from scratch.
+    fn assert_renders_like_replay(history: &History) {
+        for layer in [0, 1] {
+            let mut rendered = Raster::new(SIZE, SIZE);
+            history.render_layer(&mut rendered, layer);
+            let replayed =
+                Raster::from_commands(SIZE, SIZE, &layer_commands(history.undo_commands(), layer));
+            assert!(
+                rendered == replayed,
+                "layer {} differs from a replay",
+                layer
+            );
+        }
+    }
+
+    fn checkpoints_on(history: &History, layer: LayerId) -> Vec<usize> {
+        let mut entries: Vec<usize> = history
+            .checkpoints
+            .borrow()
+            .iter()
+            .filter(|checkpoint| checkpoint.layer == layer)
+            .map(|checkpoint| checkpoint.entries)
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn checkpoints_render_like_a_full_replay() {
+        let mut history = History::default();
+        lines(&mut history, 0, 0..45);
+        lines(&mut history, 1, 0..25);
+        assert_renders_like_replay(&history);
+        assert_eq!(checkpoints_on(&history, 0), [20, 40]);
+
+        // both reach back past the checkpoints
+        history.record(LayerCommand {
+            layer: 0,
+            command: Command::Delete(25),
+        });
+        assert_renders_like_replay(&history);
+        history.record(moved(vec![5, 30]));
+        lines(&mut history, 0, 45..70);
+        assert_renders_like_replay(&history);
+        let moved_node = history.current().unwrap() - 25;
+
+        history.undo();
+        history.undo();
+        assert_renders_like_replay(&history);
+        history.redo();
+        assert_renders_like_replay(&history);
+
+        for _ in 0..30 {
+            history.undo();
+        }
+        assert_renders_like_replay(&history);
+        lines(&mut history, 0, 100..130);
+        assert_renders_like_replay(&history);
+
+        history.jump(Some(moved_node));
+        assert_renders_like_replay(&history);
+        history.jump(Some(10));
+        assert_renders_like_replay(&history);
+        history.jump(None);
+        assert_renders_like_replay(&history);
+        history.jump(Some(moved_node + 25));
+        assert_renders_like_replay(&history);
+    }
+
+    #[test]
+    fn earlier_nodes_render_like_a_replay_of_their_path() {
+        let mut history = History::default();
+        lines(&mut history, 0, 0..50);
+        history.record(moved(vec![3, 45]));
+        lines(&mut history, 0, 50..60);
+        assert_renders_like_replay(&history);
+        history.jump(Some(30));
+        lines(&mut history, 0, 100..110);
+        let checkpoints = checkpoints_on(&history, 0);
+
+        for node in [None, Some(10), Some(45), Some(55), Some(60), Some(65)] {
+            let mut rendered = Raster::new(SIZE, SIZE);
+            history.render_layer_at(&mut rendered, 0, node);
+            let replayed =
+                Raster::from_commands(SIZE, SIZE, &layer_commands(&history.entries_to(node), 0));
+            assert!(
+                rendered == replayed,
+                "node {:?} differs from a replay",
+                node
+            );
+        }
+        assert_eq!(checkpoints_on(&history, 0), checkpoints);
+    }
+
+    #[test]
+    fn thumbnails_are_drawn_again_for_other_layer_settings() {
+        let mut history = History::default();
+        lines(&mut history, 0, 0..3);
+        let mut layers = vec![Layer::new(0, "Layer 1".to_string())];
+        let drawn = std::cell::Cell::new(0);
+        let thumbnail = |layers: &[Layer]| {
+            history.thumbnail(Some(1), layers, || {
+                drawn.set(drawn.get() + 1);
+                format!("thumbnail {}", drawn.get())
+            })
+        };
+
+        assert_eq!(thumbnail(&layers), "thumbnail 1");
+        assert_eq!(thumbnail(&layers), "thumbnail 1");
+        layers[0].opacity = 0.5;
+        assert_eq!(thumbnail(&layers), "thumbnail 2");
+        layers[0].visible = false;
+        assert_eq!(thumbnail(&layers), "thumbnail 3");
+        layers[0].locked = true;
+        assert_eq!(thumbnail(&layers), "thumbnail 3");
+    }
+
+    #[test]
+    fn only_the_latest_checkpoints_are_kept_per_layer() {
+        let mut history = History::default();
+        lines(&mut history, 0, 0..400);
+        lines(&mut history, 1, 0..45);
+        assert_renders_like_replay(&history);
+
+        let latest: Vec<usize> = (0..MAX_CHECKPOINTS)
+            .rev()
+            .map(|i| 400 - i * CHECKPOINT_INTERVAL)
+            .collect();
+        assert_eq!(checkpoints_on(&history, 0), latest);
+        assert_eq!(checkpoints_on(&history, 1), [20, 40]);
+
+        // taking more on another layer leaves these alone
+        lines(&mut history, 1, 45..300);
+        assert_renders_like_replay(&history);
+        assert_eq!(checkpoints_on(&history, 0), latest);
+        assert_eq!(checkpoints_on(&history, 1).len(), MAX_CHECKPOINTS);
+    }
+
+    #[test]
+    fn flattened_images_do_not_count_against_the_budget() {
+        let budget = HistoryBudget {
+            max_entries: 40,
+            ..HistoryBudget::default()
+        };
+        let mut history = History::default();
+        lines(&mut history, 0, 0..50);
+        lines(&mut history, 1, 0..10);
+        assert!(history.over_budget(budget));
+
+        let flattened = history.compact(budget, &mut Raster::new(SIZE, SIZE));
+        assert!(!flattened.is_empty());
+        assert!(!history.over_budget(budget));
+        // right at the goal, leaving out the image
+        let images = history
+            .undo_commands()
+            .iter()
+            .filter(|entry| matches!(entry.command, Command::Flattened(_)))
+            .count();
+        assert_eq!(
+            history.undo_commands().len() - images,
+            budget.max_entries * 3 / 4
+        );
+        assert_renders_like_replay(&history);
+    }
+
+    #[test]
+    fn redo_follows_the_branch_last_visited() {
+        let mut history = History::default();
+        lines(&mut history, 0, 0..3);
+        history.undo();
+        history.undo();
+        history.record(line(0, 3));
+        assert_eq!(history.redo(), None);
+
+        // drawing after an undo makes the new branch the one redo goes to
+        history.undo();
+        assert_eq!(history.redo(), Some(&line(0, 3)));
+        assert_eq!(history.undo_commands(), [line(0, 0), line(0, 3)]);
+
+        // and jumping back to the old branch makes it the one again
+        history.jump(Some(2));
+        history.undo();
+        history.undo();
+        assert_eq!(history.redo(), Some(&line(0, 1)));
+        assert_eq!(history.redo(), Some(&line(0, 2)));
+        assert_eq!(history.node_state(3), NodeState::Abandoned);
+    }
+
+    #[test]
+    fn saved_histories_keep_every_branch() {
+        let mut history = History::default();
+        lines(&mut history, 0, 0..3);
+        history.undo();
+        history.undo();
+        lines(&mut history, 1, 3..5);
+        history.undo();
+
+        let mut opened = History::from_saved(history.saved());
+        assert_eq!(opened.saved(), history.saved());
+        assert_eq!(opened.current(), Some(3));
+        assert_eq!(opened.undo_commands(), [line(0, 0), line(1, 3)]);
+        assert_eq!(opened.nodes(), history.nodes());
+        assert_eq!(opened.node_state(2), NodeState::Abandoned);
+        assert_eq!(
+            opened.entries_to(Some(2)),
+            [line(0, 0), line(0, 1), line(0, 2)]
+        );
+        assert_eq!(opened.redo(), Some(&line(1, 4)));
+        assert_eq!(opened.redo(), None);
+    }
+
+    #[test]
+    fn saved_histories_that_are_not_trees_are_refused() {
+        let mut history = History::default();
+        lines(&mut history, 0, 0..2);
+        assert_eq!(history.saved().check(), Ok(()));
+
+        let mut saved = history.saved();
+        saved.nodes[0].parent = Some(1);
+        assert!(saved.check().is_err());
+
+        let mut saved = history.saved();
+        saved.nodes[1].redo = Some(0);
+        assert!(saved.check().is_err());
+
+        let mut saved = history.saved();
+        saved.root_redo = Some(1);
+        assert!(saved.check().is_err());
+
+        let mut saved = history.saved();
+        saved.current = Some(2);
+        assert!(saved.check().is_err());
+    }
+}
@@ -0,0 +1,378 @@
+// keymap.rs
+//
+// Keyboard shortcuts. Every action that can have a shortcut is an `Action`,
+// and the `Keymap` binds key combinations to them. The keymap is edited in
+// the shortcuts panel and kept in the browser's local storage, so changes
+// survive a reload.
+
+use crate::drawing::ToolMode;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Where the keymap is kept in local storage.
+pub const STORAGE_KEY: &str = "keymap";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Undo,
+    Redo,
+    ClearCanvas,
+    Save,
+    Tool(ToolMode),
+    ThinnerLine,
+    ThickerLine,
+    Cut,
+    Copy,
+    Paste,
+    DeleteSelection,
+    NudgeLeft,
+    NudgeRight,
+    NudgeUp,
+    NudgeDown,
+    ZoomIn,
+    ZoomOut,
+    ZoomToFit,
+    ActualSize,
+}
+
+impl Action {
+    /// Every action, in the order the shortcuts panel lists them.
+    pub const ALL: [Action; 30] = [
+        Action::Undo,
+        Action::Redo,
+        Action::ClearCanvas,
+        Action::Save,
+        Action::Tool(ToolMode::Pen),
+        Action::Tool(ToolMode::Eraser),
+        Action::Tool(ToolMode::ObjectEraser),
+        Action::Tool(ToolMode::Line),
+        Action::Tool(ToolMode::Circle),
+        Action::Tool(ToolMode::Rectangle),
+        Action::Tool(ToolMode::Polygon),
+        Action::Tool(ToolMode::Fill),
+        Action::Tool(ToolMode::Eyedropper),
+        Action::Tool(ToolMode::Select),
+        Action::Tool(ToolMode::Marquee),
+        Action::Tool(ToolMode::Lasso),
+        Action::ThinnerLine,
+        Action::ThickerLine,
+        Action::Cut,
+        Action::Copy,
+        Action::Paste,
+        Action::DeleteSelection,
+        Action::NudgeLeft,
+        Action::NudgeRight,
+        Action::NudgeUp,
+        Action::NudgeDown,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::ZoomToFit,
+        Action::ActualSize,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::ClearCanvas => "Clear layer",
+            Action::Save => "Save drawing",
+            Action::Tool(ToolMode::Pen) => "Pen",
+            Action::Tool(ToolMode::Eraser) => "Eraser",
+            Action::Tool(ToolMode::ObjectEraser) => "Object eraser",
+            Action::Tool(ToolMode::Line) => "Line",
+            Action::Tool(ToolMode::Circle) => "Circle",
+            Action::Tool(ToolMode::Rectangle) => "Rectangle",
+            Action::Tool(ToolMode::Polygon) => "Polygon",
+            Action::Tool(ToolMode::Fill) => "Paint bucket",
+            Action::Tool(ToolMode::Eyedropper) => "Eyedropper",
+            Action::Tool(ToolMode::Select) => "Select",
+            Action::Tool(ToolMode::Marquee) => "Marquee",
+            Action::Tool(ToolMode::Lasso) => "Lasso",
+            Action::ThinnerLine => "Thinner stroke",
+            Action::ThickerLine => "Thicker stroke",
+            Action::Cut => "Cut pixels",
+            Action::Copy => "Copy pixels",
+            Action::Paste => "Paste pixels",
+            Action::DeleteSelection => "Delete pixels",
+            Action::NudgeLeft => "Nudge left",
+            Action::NudgeRight => "Nudge right",
+            Action::NudgeUp => "Nudge up",
+            Action::NudgeDown => "Nudge down",
+            Action::ZoomIn => "Zoom in",
+            Action::ZoomOut => "Zoom out",
+            Action::ZoomToFit => "Zoom to fit",
+            Action::ActualSize => "Zoom to 100%",
+        }
+    }
+}
+
+/// A key together with the modifiers held down with it. Letters are kept in
+/// lower case, so Shift only counts through `shift`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyCombo {
+    /// The `key` of the keyboard event, like "z", "[" or "Delete".
+    pub key: String,
+    /// Control, or Command on a Mac.
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl KeyCombo {
+    pub fn new(key: &str, ctrl: bool, shift: bool, alt: bool) -> Self {
+        let key = match key.chars().count() {
+            1 => key.to_lowercase(),
+            _ => key.to_string(),
+        };
+        Self {
+            key,
+            ctrl,
+            shift,
+            alt,
+        }
+    }
+
+    /// Modifier keys on their own can't be shortcuts, they only change one.
+    pub fn is_modifier(key: &str) -> bool {
+        matches!(
+            key,
+            "Control" | "Shift" | "Alt" | "Meta" | "AltGraph" | "CapsLock"
+        )
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        write!(f, "{}", self.key.to_uppercase())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub action: Action,
+    pub keys: KeyCombo,
+}
+
+/// Which key combination runs which action. An action can have any number
+/// of bindings, but a key combination should run only one action.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keymap {
+    pub bindings: Vec<Binding>,
+    /// Actions whose shortcuts were removed on purpose, so loading the keymap
+    /// doesn't give them their default keys back.
+    #[serde(default)]
+    pub unbound: Vec<Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let key = |key: &str| KeyCombo::new(key, false, false, false);
+        let ctrl = |key: &str| KeyCombo::new(key, true, false, false);
+        let defaults = [
+            (Action::Undo, ctrl("z")),
+            (Action::Redo, KeyCombo::new("z", true, true, false)),
+            (Action::Redo, ctrl("y")),
+            (
+                Action::ClearCanvas,
+                KeyCombo::new("Delete", true, false, false),
+            ),
+            (Action::Save, ctrl("s")),
+            (Action::Tool(ToolMode::Pen), key("b")),
+            (Action::Tool(ToolMode::Eraser), key("e")),
+            (Action::Tool(ToolMode::Line), key("l")),
+            (Action::Tool(ToolMode::Circle), key("c")),
+            (Action::Tool(ToolMode::Rectangle), key("r")),
+            (Action::Tool(ToolMode::Polygon), key("p")),
+            (Action::Tool(ToolMode::Fill), key("g")),
+            (Action::Tool(ToolMode::Eyedropper), key("i")),
+            (Action::Tool(ToolMode::Select), key("v")),
+            (Action::Tool(ToolMode::Marquee), key("m")),
+            (Action::ThinnerLine, key("[")),
+            (Action::ThickerLine, key("]")),
+            (Action::Cut, ctrl("x")),
+            (Action::Copy, ctrl("c")),
+            (Action::Paste, ctrl("v")),
+            (Action::DeleteSelection, key("Delete")),
+            (Action::NudgeLeft, key("ArrowLeft")),
+            (Action::NudgeRight, key("ArrowRight")),
+            (Action::NudgeUp, key("ArrowUp")),
+            (Action::NudgeDown, key("ArrowDown")),
+            (Action::ZoomIn, ctrl("=")),
+            (Action::ZoomOut, ctrl("-")),
+            (Action::ZoomToFit, ctrl("0")),
+            (Action::ActualSize, KeyCombo::new("0", true, false, true)),
+        ];
+        Self {
+            bindings: defaults
+                .into_iter()
+                .map(|(action, keys)| Binding { action, keys })
+                .collect(),
+            unbound: vec![],
+        }
+    }
+}
+
+impl Keymap {
+    /// The action bound to `keys`. When a conflict slipped through, the first
+    /// binding wins.
+    pub fn action(&self, keys: &KeyCombo) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.keys == *keys)
+            .map(|binding| binding.action)
+    }
+
+    pub fn keys(&self, action: Action) -> Vec<KeyCombo> {
+        self.bindings
+            .iter()
+            .filter(|binding| binding.action == action)
+            .map(|binding| binding.keys.clone())
+            .collect()
+    }
+
+    /// Replaces the bindings of `action` with `keys`, unless another action
+    /// already uses them.
+    pub fn rebind(&mut self, action: Action, keys: KeyCombo) -> Result<(), String> {
+        match self.action(&keys) {
+            Some(other) if other != action => Err(format!(
+                "{} is already the shortcut for {}",
+                keys,
+                other.label()
+            )),
+            _ => {
+                self.unbind(action);
+                self.unbound.retain(|unbound| *unbound != action);
+                self.bindings.push(Binding { action, keys });
+                Ok(())
+            }
+        }
+    }
+
+    pub fn unbind(&mut self, action: Action) {
+        self.bindings.retain(|binding| binding.action != action);
+        if !self.unbound.contains(&action) {
+            self.unbound.push(action);
+        }
+    }
+
+    /// Key combinations bound to more than one action, with those actions.
+    /// Only a keymap edited outside the app can have any.
+    pub fn conflicts(&self) -> Vec<(KeyCombo, Vec<Action>)> {
+        let mut conflicts: Vec<(KeyCombo, Vec<Action>)> = vec![];
+        for binding in &self.bindings {
+            let actions: Vec<Action> = self
+                .bindings
+                .iter()
+                .filter(|other| other.keys == binding.keys)
+                .map(|other| other.action)
+                .fold(vec![], |mut actions, action| {
+                    if !actions.contains(&action) {
+                        actions.push(action);
+                    }
+                    actions
+                });
+            let known = conflicts.iter().any(|(keys, _)| *keys == binding.keys);
+            if actions.len() > 1 && !known {
+                conflicts.push((binding.keys.clone(), actions));
+            }
+        }
+        conflicts
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// A stored keymap, with the default bindings of every action it says
+    /// nothing about, such as actions added since it was saved.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str::<Self>(json)
+            .map(|stored| stored.over(Keymap::default()))
+            .map_err(|error| format!("not a keymap: {}", error))
+    }
+
+    /// These bindings, plus those in `defaults` for actions that have none and
+    /// weren't unbound. A default whose keys are already taken is left out.
+    fn over(mut self, defaults: Keymap) -> Self {
+        let missing: Vec<Binding> = defaults
+            .bindings
+            .into_iter()
+            .filter(|binding| {
+                self.keys(binding.action).is_empty()
+                    && !self.unbound.contains(&binding.action)
+                    && self.action(&binding.keys).is_none()
+            })
+            .collect();
+        self.bindings.extend(missing);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctrl(key: &str) -> KeyCombo {
+        KeyCombo::new(key, true, false, false)
+    }
+
+    /// A keymap saved before the zoom actions existed.
+    fn without_zoom() -> Keymap {
+        let mut keymap = Keymap::default();
+        keymap.bindings.retain(|binding| {
+            ![
+                Action::ZoomIn,
+                Action::ZoomOut,
+                Action::ZoomToFit,
+                Action::ActualSize,
+            ]
+            .contains(&binding.action)
+        });
+        keymap
+    }
+
+    #[test]
+    fn new_actions_get_their_default_keys() {
+        let loaded = Keymap::from_json(&without_zoom().to_json()).unwrap();
+        assert_eq!(loaded.keys(Action::ZoomIn), [ctrl("=")]);
+        assert_eq!(loaded.action(&ctrl("0")), Some(Action::ZoomToFit));
+    }
+
+    #[test]
+    fn stored_bindings_win_over_the_defaults() {
+        let mut stored = without_zoom();
+        stored.rebind(Action::Save, ctrl("=")).unwrap();
+        let loaded = Keymap::from_json(&stored.to_json()).unwrap();
+        assert_eq!(loaded.keys(Action::Save), [ctrl("=")]);
+        assert!(loaded.keys(Action::ZoomIn).is_empty());
+        assert!(loaded.conflicts().is_empty());
+    }
+
+    #[test]
+    fn unbound_actions_stay_unbound() {
+        let mut stored = Keymap::default();
+        stored.unbind(Action::Undo);
+        let loaded = Keymap::from_json(&stored.to_json()).unwrap();
+        assert!(loaded.keys(Action::Undo).is_empty());
+
+        let mut rebound = loaded;
+        rebound.rebind(Action::Undo, ctrl("u")).unwrap();
+        let loaded = Keymap::from_json(&rebound.to_json()).unwrap();
+        assert_eq!(loaded.keys(Action::Undo), [ctrl("u")]);
+    }
+
+    #[test]
+    fn keymaps_saved_before_unbinding_was_stored_still_load() {
+        let loaded = Keymap::from_json(r#"{"bindings":[]}"#).unwrap();
+        assert_eq!(loaded.bindings, Keymap::default().bindings);
+    }
+}
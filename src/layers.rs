@@ -0,0 +1,191 @@
+// layers.rs
+//
+// Layers are drawn on their own stacked canvases. The history stays a single
+// chronological list, with every entry tagged by the layer it was drawn on,
+// so undo and redo only ever have to redraw that one layer. Deleting or
+// transforming a whole command is an entry of its own too, so it can be
+// undone like drawing.
+
+use crate::drawing::Command;
+use crate::geometry::transform_command;
+use serde::{Deserialize, Serialize};
+
+pub type LayerId = u32;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Layer {
+    pub id: LayerId,
+    pub name: String,
+    pub visible: bool,
+    pub locked: bool,
+    /// 0.0 (transparent) to 1.0 (opaque).
+    pub opacity: f64,
+}
+
+impl Layer {
+    pub fn new(id: LayerId, name: String) -> Self {
+        Self {
+            id,
+            name,
+            visible: true,
+            locked: false,
+            opacity: 1.0,
+        }
+    }
+
+    /// Whether tools may draw on it. Hidden layers are protected too, so
+    /// nothing gets drawn where it can't be seen.
+    pub fn is_editable(&self) -> bool {
+        self.visible && !self.locked
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LayerCommand {
+    pub layer: LayerId,
+    pub command: Command,
+}
+
+/// A layer numbered after the ones already in `layers`, with an unused id.
+pub fn new_layer(layers: &[Layer]) -> Layer {
+    let id = layers.iter().map(|layer| layer.id + 1).max().unwrap_or(0);
+    Layer::new(id, format!("Layer {}", layers.len() + 1))
+}
+
+/// The part of the history drawn on `layer`, in order, with deletions and
+/// transformations applied to the commands they target and groups taken apart.
+pub fn layer_commands(history: &[LayerCommand], layer: LayerId) -> Vec<Command> {
+    live_commands(history, layer)
+        .into_iter()
+        .flat_map(|(_, command)| match command {
+            Command::Group(commands) => commands,
+            command => vec![command],
+        })
+        .collect()
+}
+
+/// The commands on `layer` that haven't been deleted, as they stand after
+/// every transformation, each with its position among all of the layer's
+/// entries, which is what `Command::Delete` and `Command::Transform` refer to.
+/// Positions are counted within the layer so that removing another layer's
+/// history doesn't shift them. Nothing from before the last clear is live,
+/// since none of it can show.
+pub fn live_commands(history: &[LayerCommand], layer: LayerId) -> Vec<(usize, Command)> {
+    let mut live: Vec<(usize, Command)> = vec![];
+    history
+        .iter()
+        .filter(|entry| entry.layer == layer)
+        .enumerate()
+        .for_each(|(index, entry)| match &entry.command {
+            // what a group draws stays together as one command, at the group's position
+            Command::Group(commands) => {
+                let mut drawn: Vec<Command> = vec![];
+                commands.iter().for_each(|command| {
+                    if *command == Command::EraseCanvas {
+                        live.clear();
+                        drawn.clear();
+                    }
+                    if !change_live(&mut live, command) {
+                        drawn.push(command.clone());
+                    }
+                });
+                if !drawn.is_empty() {
+                    live.push((index, Command::Group(drawn)));
+                }
+            }
+            command => {
+                if *command == Command::EraseCanvas {
+                    live.clear();
+                }
+                if !change_live(&mut live, command) {
+                    live.push((index, command.clone()));
+                }
+            }
+        });
+    live
+}
+
+// PRIVATE
+
+/// Applies `command` to the live commands if it deletes or transforms some,
+/// and tells whether it did.
+fn change_live(live: &mut Vec<(usize, Command)>, command: &Command) -> bool {
+    match command {
+        Command::Delete(target) => live.retain(|(live_index, _)| live_index != target),
+        Command::Transform(transformation) => live
+            .iter_mut()
+            .filter(|(live_index, _)| transformation.targets.contains(live_index))
+            .for_each(|(_, command)| *command = transform_command(command, transformation)),
+        _ => return false,
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drawing::{Color, FillMode, ShapeProperties};
+    use dioxus::prelude::*;
+    use dioxus_elements::geometry::ElementPoint;
+
+    fn entry(command: Command) -> LayerCommand {
+        LayerCommand { layer: 0, command }
+    }
+
+    fn line(y: f64) -> Command {
+        Command::DrawLine(ShapeProperties {
+            start_point: ElementPoint::new(0.0, y),
+            end_point: ElementPoint::new(10.0, y),
+            color: Color(0.0, 0.0, 0.0, 1.0),
+            line_width: 1.0,
+            fill_color: Color(255.0, 255.0, 255.0, 1.0),
+            fill_mode: FillMode::Stroke,
+        })
+    }
+
+    fn positions(live: Vec<(usize, Command)>) -> Vec<usize> {
+        live.into_iter().map(|(index, _)| index).collect()
+    }
+
+    #[test]
+    fn nothing_before_a_clear_is_live() {
+        let history = [
+            entry(line(1.0)),
+            entry(line(2.0)),
+            entry(Command::EraseCanvas),
+            entry(line(3.0)),
+        ];
+        assert_eq!(positions(live_commands(&history, 0)), [2, 3]);
+        assert_eq!(
+            layer_commands(&history, 0),
+            [Command::EraseCanvas, line(3.0)]
+        );
+    }
+
+    #[test]
+    fn a_clear_inside_a_group_drops_what_came_before_it() {
+        let history = [
+            entry(line(1.0)),
+            entry(Command::group(vec![
+                line(2.0),
+                Command::EraseCanvas,
+                line(3.0),
+            ])),
+        ];
+        assert_eq!(
+            live_commands(&history, 0),
+            [(1, Command::Group(vec![Command::EraseCanvas, line(3.0)]))]
+        );
+    }
+
+    #[test]
+    fn deleting_what_a_clear_hid_changes_nothing() {
+        let history = [
+            entry(line(1.0)),
+            entry(Command::EraseCanvas),
+            entry(line(2.0)),
+            entry(Command::Delete(0)),
+        ];
+        assert_eq!(positions(live_commands(&history, 0)), [1, 2]);
+    }
+}
@@ -1,13 +1,32 @@
 // main.rs
 
 mod app_state;
+mod color_space;
 mod drawing;
+mod file_format;
+mod geometry;
+mod history;
+mod keymap;
+mod layers;
+mod palette;
+mod raster;
+mod svg;
+mod viewport;
 use crate::app_state::AppState;
 
+use crate::color_space::Hsv;
 use crate::drawing::*;
+use crate::history::{History, BUDGET_STORAGE_KEY};
+use crate::keymap::{KeyCombo, Keymap, STORAGE_KEY};
+use crate::layers::Layer;
+use crate::palette::Palette;
+use crate::viewport::Viewport;
 
 mod components;
-use components::{ColorPicker, DrawCanvas, LineWidthSlider, MenuBar, ToolBar};
+use components::{
+    ColorPicker, DrawCanvas, HistoryPanel, KeymapPanel, LayersPanel, LineWidthSlider, MenuBar,
+    PalettePanel, ToolBar, ToolOptions,
+};
 #[allow(unused_imports)]
 use dioxus::logger::tracing;
 use dioxus::prelude::*;
@@ -22,21 +41,38 @@ fn main() {
 #[allow(non_snake_case)]
 pub fn App() -> Element {
     // INITIALIZE STATE
-    let _app_state = use_context_provider(|| AppState {
+    let mut app_state = use_context_provider(|| AppState {
         current_point: Signal::new(ElementPoint::zero()),
         tool_mode: Signal::new(ToolMode::Pen),
         tool_active: Signal::new(false),
 
-        undo_commands: Signal::new(vec![]),
-        redo_commands: Signal::new(vec![]),
+        history: Signal::new(History::default()),
+        history_budget: Signal::new(
+            load_setting(BUDGET_STORAGE_KEY)
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default(),
+        ),
         current_path: Signal::new(vec![]),
 
-        rgb_color: Signal::new(Color(0.0, 0.0, 0.0)),
-        hue: Signal::new(0.0),
-        sat_val_coord: Signal::new((0.0, 0.0)),
+        rgb_color: Signal::new(Color(0.0, 0.0, 0.0, 1.0)),
+        hsv: Signal::new(Hsv {
+            hue: 0.0,
+            saturation: 0.0,
+            value: 0.0,
+        }),
+        fill_color: Signal::new(Color(255.0, 255.0, 255.0, 1.0)),
+        fill_mode: Signal::new(FillMode::Stroke),
+        palette: Signal::new(Palette::new("Untitled".to_string())),
 
         line_width: Signal::new(1.0),
 
+        bucket_tolerance: Signal::new(32),
+        bucket_connectivity: Signal::new(Connectivity::Four),
+        eyedropper_size: Signal::new(1),
+        selection: Signal::new(vec![]),
+        pixel_selection: Signal::new(vec![]),
+        clipboard: Signal::new(None),
+
         canvas_cursor: Signal::new("default".to_string()),
         point_down: Signal::new(ElementPoint::zero()),
 
@@ -46,18 +82,73 @@ pub fn App() -> Element {
                 width: 1500.0,
                 height: 800.0,
             },
+            layer: None,
+            scale: 1.0,
         }),
+        viewport: Signal::new(Viewport::default()),
+        space_held: Signal::new(false),
         shape_properties: Signal::new(ShapeProperties {
             start_point: ElementPoint::zero(),
             end_point: ElementPoint::zero(),
-            color: Color(0.0, 0.0, 0.0),
+            color: Color(0.0, 0.0, 0.0, 1.0),
             line_width: 1.0,
+            fill_color: Color(255.0, 255.0, 255.0, 1.0),
+            fill_mode: FillMode::Stroke,
         }),
+
+        layers: Signal::new(vec![Layer::new(0, "Layer 1".to_string())]),
+        active_layer: Signal::new(0),
+
+        keymap: Signal::new(
+            load_setting(STORAGE_KEY)
+                .and_then(|json| Keymap::from_json(&json).ok())
+                .unwrap_or_default(),
+        ),
     });
+
+    // HANDLERS
+    // shortcuts work anywhere in the app, except while typing into a field
+    let key_handler = move |event: Event<KeyboardData>| {
+        if typing_in_field() {
+            return;
+        }
+        let modifiers = event.modifiers();
+        let keys = KeyCombo::new(
+            &event.key().to_string(),
+            modifiers.ctrl() || modifiers.meta(),
+            modifiers.shift(),
+            modifiers.alt(),
+        );
+        let action = app_state.keymap.peek().action(&keys);
+        if let Some(action) = action {
+            // keep the browser's own Ctrl+S and the like from running as well
+            event.prevent_default();
+            app_state.run(action);
+        } else if keys.key == " " {
+            // not scrolling the page
+            event.prevent_default();
+            if !*app_state.space_held.peek() {
+                app_state.space_held.set(true);
+            }
+        }
+    };
+    let key_up_handler = move |event: Event<KeyboardData>| {
+        if event.key().to_string() == " " {
+            app_state.space_held.set(false);
+        }
+    };
+
     rsx! {
         document::Stylesheet { href: MAIN_CSS }
         div{
             class: "main-div",
+            // focusable, so clicks on the canvas keep the keyboard in the app
+            tabindex: 0,
+            onkeydown: key_handler,
+            onkeyup: key_up_handler,
+            onmounted: move |event| async move {
+                let _ = event.set_focus(true).await;
+            },
             div {
                 class: "tool-div",
                 h1 {
@@ -66,7 +157,12 @@ pub fn App() -> Element {
                 MenuBar {}
                 ToolBar {}
                 LineWidthSlider {}
+                ToolOptions {}
                 ColorPicker {}
+                PalettePanel {}
+                LayersPanel {}
+                HistoryPanel {}
+                KeymapPanel {}
             }
 
             DrawCanvas {}
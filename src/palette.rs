@@ -0,0 +1,231 @@
+// palette.rs
+//
+// Swatch palettes and the two file formats they are shared in: GIMP's `.gpl`
+// and a small JSON format that keeps alpha. Also picks the recently used
+// colors out of the history, so they only ever show what was really drawn.
+
+use crate::color_space::{from_hex, to_hex};
+use crate::drawing::{Color, Command};
+use crate::layers::{live_commands, LayerCommand, LayerId};
+use serde::{Deserialize, Serialize};
+
+pub const GPL_EXTENSION: &str = "gpl";
+pub const JSON_EXTENSION: &str = "palette.json";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Swatch {
+    pub color: Color,
+    /// Optional, empty when the swatch has no name.
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    pub swatches: Vec<Swatch>,
+}
+
+impl Palette {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            swatches: vec![],
+        }
+    }
+
+    /// Adds `color` at the end, unless the palette already has it.
+    pub fn add(&mut self, color: Color) {
+        if !self.swatches.iter().any(|swatch| swatch.color == color) {
+            self.swatches.push(Swatch {
+                color,
+                name: String::new(),
+            });
+        }
+    }
+
+    /// GIMP palettes have no alpha, so every color is written opaque.
+    pub fn to_gpl(&self) -> String {
+        let mut gpl = format!("GIMP Palette\nName: {}\nColumns: 8\n#\n", self.name);
+        for swatch in &self.swatches {
+            let byte = |value: f64| value.round().clamp(0.0, 255.0) as u8;
+            let (r, g, b) = (
+                byte(swatch.color.0),
+                byte(swatch.color.1),
+                byte(swatch.color.2),
+            );
+            gpl.push_str(&format!("{:3} {:3} {:3}\t{}\n", r, g, b, swatch.name));
+        }
+        gpl
+    }
+
+    pub fn from_gpl(gpl: &str) -> Result<Self, String> {
+        let mut lines = gpl.lines().enumerate();
+        if lines.next().map(|(_, line)| line.trim()) != Some("GIMP Palette") {
+            return Err("not a GIMP palette, the first line isn't 'GIMP Palette'".to_string());
+        }
+
+        let mut palette = Self::new("Untitled".to_string());
+        for (index, line) in lines {
+            let line = line.trim();
+            if let Some(name) = line.strip_prefix("Name:") {
+                palette.name = name.trim().to_string();
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let mut channel = || {
+                fields
+                    .next()
+                    .and_then(|field| field.parse::<u8>().ok())
+                    .map(f64::from)
+                    .ok_or(format!(
+                        "line {}: expected three numbers from 0 to 255",
+                        index + 1
+                    ))
+            };
+            let color = Color(channel()?, channel()?, channel()?, 1.0);
+            palette.swatches.push(Swatch {
+                color,
+                name: fields.collect::<Vec<_>>().join(" "),
+            });
+        }
+        Ok(palette)
+    }
+
+    pub fn to_json(&self) -> String {
+        let file = PaletteFile {
+            name: self.name.clone(),
+            colors: self
+                .swatches
+                .iter()
+                .map(|swatch| SwatchFile {
+                    hex: to_hex(swatch.color),
+                    name: swatch.name.clone(),
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&file).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let file = serde_json::from_str::<PaletteFile>(json)
+            .map_err(|error| format!("not a palette file: {}", error))?;
+        let swatches = file
+            .colors
+            .into_iter()
+            .map(|swatch| {
+                let color = from_hex(&swatch.hex)
+                    .map_err(|error| format!("color '{}': {}", swatch.hex, error))?;
+                Ok(Swatch {
+                    color,
+                    name: swatch.name,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            name: file.name,
+            swatches,
+        })
+    }
+}
+
+/// The last `limit` different colors drawn with, most recent first. Only
+/// what is still on the layers counts, not what was deleted or cleared.
+pub fn recent_colors(history: &[LayerCommand], limit: usize) -> Vec<Color> {
+    let mut layers: Vec<LayerId> = vec![];
+    history.iter().for_each(|entry| {
+        if !layers.contains(&entry.layer) {
+            layers.push(entry.layer);
+        }
+    });
+    // positions within a layer, mapped back to where they are in the history
+    let mut live: Vec<(usize, Command)> = layers
+        .into_iter()
+        .flat_map(|layer| {
+            let indices: Vec<usize> = (0..history.len())
+                .filter(|index| history[*index].layer == layer)
+                .collect();
+            live_commands(history, layer)
+                .into_iter()
+                .map(move |(position, command)| (indices[position], command))
+        })
+        .collect();
+    live.sort_by_key(|(index, _)| std::cmp::Reverse(*index));
+
+    let mut recent: Vec<Color> = vec![];
+    for color in live.iter().flat_map(|(_, command)| command.colors()) {
+        if recent.len() == limit {
+            break;
+        }
+        if !recent.contains(&color) {
+            recent.push(color);
+        }
+    }
+    recent
+}
+
+// PRIVATE
+
+/// The JSON format: `{"name": "Brand", "colors": [{"hex": "#ff0000", "name": "Red"}]}`.
+#[derive(Serialize, Deserialize)]
+struct PaletteFile {
+    name: String,
+    colors: Vec<SwatchFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SwatchFile {
+    hex: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    name: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drawing::{FillMode, ShapeProperties};
+    use dioxus::prelude::*;
+    use dioxus_elements::geometry::ElementPoint;
+
+    fn line(layer: LayerId, red: f64) -> LayerCommand {
+        LayerCommand {
+            layer,
+            command: Command::DrawLine(ShapeProperties {
+                start_point: ElementPoint::new(0.0, 0.0),
+                end_point: ElementPoint::new(10.0, 0.0),
+                color: Color(red, 0.0, 0.0, 1.0),
+                line_width: 1.0,
+                fill_color: Color(255.0, 255.0, 255.0, 1.0),
+                fill_mode: FillMode::Stroke,
+            }),
+        }
+    }
+
+    fn reds(colors: Vec<Color>) -> Vec<f64> {
+        colors.into_iter().map(|color| color.0).collect()
+    }
+
+    #[test]
+    fn recent_colors_are_newest_first_across_layers() {
+        let history = [line(0, 1.0), line(1, 2.0), line(0, 3.0), line(1, 1.0)];
+        assert_eq!(reds(recent_colors(&history, 10)), [1.0, 3.0, 2.0]);
+        assert_eq!(reds(recent_colors(&history, 2)), [1.0, 3.0]);
+    }
+
+    #[test]
+    fn recent_colors_leave_out_deleted_commands() {
+        let history = [
+            line(0, 1.0),
+            line(1, 2.0),
+            line(0, 3.0),
+            LayerCommand {
+                layer: 0,
+                command: Command::Delete(1),
+            },
+        ];
+        assert_eq!(reds(recent_colors(&history, 10)), [2.0, 1.0]);
+    }
+}
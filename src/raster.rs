@@ -0,0 +1,835 @@
+// raster.rs
+//
+// Headless CPU backend for `RenderTarget`. Commands render into a plain RGBA
+// buffer, so drawings can be produced and compared outside a browser.
+//
+// Coverage is sampled once at each pixel center with no anti-aliasing, which
+// keeps the output pixel-exact across platforms. Strokes follow the canvas
+// defaults: butt caps, mitered rectangle corners and joined path segments.
+// Path and polygon joins are rounded rather than mitered.
+// Translucent colors blend source-over, once per primitive, so a stroke that
+// crosses itself doesn't get darker where it overlaps.
+
+use crate::drawing::{
+    Color, Command, Composite, Connectivity, FloodFill, PixelPatch, RenderTarget, Shape,
+};
+use crate::geometry::winding_number;
+use dioxus::prelude::*;
+use dioxus_elements::geometry::ElementPoint;
+
+pub const TRANSPARENT: [u8; 4] = [0, 0, 0, 0];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Raster {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    composite: Composite,
+}
+
+impl Raster {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+            composite: Composite::SourceOver,
+        }
+    }
+
+    pub fn from_commands(width: usize, height: usize, commands: &[Command]) -> Self {
+        let mut raster = Self::new(width, height);
+        raster.execute_commands(commands);
+        raster
+    }
+
+    /// Row-major RGBA bytes, the same layout as canvas `ImageData`.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        (y * self.width + x) * 4
+    }
+
+    fn paint(&mut self, coverage: Coverage, color: Color) {
+        let source = rgba(color);
+        let composite = self.composite;
+        coverage.covered_pixels().for_each(|(x, y)| {
+            let i = self.index(x, y);
+            let pixel = &mut self.pixels[i..i + 4];
+            let result = match composite {
+                Composite::SourceOver => source_over(pixel, source),
+                Composite::DestinationOut => destination_out(pixel, source),
+            };
+            pixel.copy_from_slice(&result);
+        });
+    }
+}
+
+impl RenderTarget for Raster {
+    fn stroke(&mut self, shape: Shape, color: Color, line_width: f64) {
+        let half = line_width / 2.0;
+        let mut coverage = Coverage::new(self.width, self.height);
+        match shape {
+            Shape::Polyline(points) => {
+                points
+                    .windows(2)
+                    .for_each(|segment| coverage.segment(segment[0], segment[1], half));
+                // interior vertices are joined so consecutive segments don't leave notches
+                if points.len() > 2 {
+                    points[1..points.len() - 1]
+                        .iter()
+                        .for_each(|point| coverage.disc(*point, half));
+                }
+            }
+            Shape::Polygon(points) => {
+                points
+                    .iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .for_each(|(start, end)| coverage.segment(*start, *end, half));
+                points.iter().for_each(|point| coverage.disc(*point, half));
+            }
+            Shape::Line(start, end) => coverage.segment(start, end, half),
+            Shape::Rectangle(start, end) => {
+                let (min, max) = corners(start, end);
+                coverage.mark(
+                    ElementPoint::new(min.x - half, min.y - half),
+                    ElementPoint::new(max.x + half, max.y + half),
+                    |p| {
+                        !(p.x > min.x + half
+                            && p.x < max.x - half
+                            && p.y > min.y + half
+                            && p.y < max.y - half)
+                    },
+                );
+            }
+            Shape::Circle(center, radius) => {
+                let outer = radius + half;
+                coverage.mark(
+                    ElementPoint::new(center.x - outer, center.y - outer),
+                    ElementPoint::new(center.x + outer, center.y + outer),
+                    |p| ((p - center).length() - radius).abs() <= half,
+                );
+            }
+        }
+        self.paint(coverage, color);
+    }
+
+    fn fill(&mut self, shape: Shape, color: Color) {
+        let mut coverage = Coverage::new(self.width, self.height);
+        match shape {
+            Shape::Polyline(points) | Shape::Polygon(points) => {
+                if let Some((min, max)) = bounds(points) {
+                    coverage.mark(min, max, |p| winding_number(points, p) != 0);
+                }
+            }
+            // a line encloses no area
+            Shape::Line(_, _) => {}
+            Shape::Rectangle(start, end) => {
+                let (min, max) = corners(start, end);
+                coverage.mark(min, max, |p| {
+                    p.x >= min.x && p.x < max.x && p.y >= min.y && p.y < max.y
+                });
+            }
+            Shape::Circle(center, radius) => {
+                coverage.mark(
+                    ElementPoint::new(center.x - radius, center.y - radius),
+                    ElementPoint::new(center.x + radius, center.y + radius),
+                    |p| (p - center).length() <= radius,
+                );
+            }
+        }
+        self.paint(coverage, color);
+    }
+
+    fn clear(&mut self) {
+        self.pixels.fill(0);
+    }
+
+    fn set_composite(&mut self, composite: Composite) {
+        self.composite = composite;
+    }
+
+    fn flood_fill(&mut self, fill: &FloodFill) {
+        flood_fill(&mut self.pixels, self.width, self.height, fill);
+    }
+
+    fn paste(&mut self, patch: &PixelPatch) {
+        for (row, source_row) in patch
+            .pixels
+            .chunks_exact(patch.width as usize * 4)
+            .enumerate()
+        {
+            let y = patch.y + row as i32;
+            for (column, source) in source_row.chunks_exact(4).enumerate() {
+                let x = patch.x + column as i32;
+                if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+                    continue;
+                }
+                let i = self.index(x as usize, y as usize);
+                let pixel = &mut self.pixels[i..i + 4];
+                let result = source_over(pixel, [source[0], source[1], source[2], source[3]]);
+                pixel.copy_from_slice(&result);
+            }
+        }
+    }
+
+    fn snapshot(&self) -> PixelPatch {
+        PixelPatch {
+            x: 0,
+            y: 0,
+            width: self.width as u32,
+            pixels: self.pixels.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: &PixelPatch) {
+        assert_eq!(
+            snapshot.pixels.len(),
+            self.pixels.len(),
+            "snapshot of another size"
+        );
+        self.pixels.copy_from_slice(&snapshot.pixels);
+    }
+}
+
+/// Makes every pixel of `patch` whose center is outside the closed `outline` transparent.
+pub fn mask_outside(patch: &mut PixelPatch, outline: &[ElementPoint]) {
+    let (x, y, width) = (patch.x, patch.y, patch.width as usize);
+    patch
+        .pixels
+        .chunks_exact_mut(4)
+        .enumerate()
+        .for_each(|(i, pixel)| {
+            let center = ElementPoint::new(
+                (x + (i % width) as i32) as f64 + 0.5,
+                (y + (i / width) as i32) as f64 + 0.5,
+            );
+            if winding_number(outline, center) == 0 {
+                pixel.copy_from_slice(&TRANSPARENT);
+            }
+        });
+}
+
+/// Flood fills row-major RGBA `pixels`. Shared with the web canvas, which runs
+/// it on its `ImageData`, so a fill replays the same way on every target.
+pub fn flood_fill(pixels: &mut [u8], width: usize, height: usize, fill: &FloodFill) {
+    let source = rgba(fill.color);
+    fill_region(pixels, width, height, fill)
+        .iter()
+        .enumerate()
+        .filter(|(_, filled)| **filled)
+        .for_each(|(i, _)| {
+            let pixel = &mut pixels[i * 4..i * 4 + 4];
+            let result = source_over(pixel, source);
+            pixel.copy_from_slice(&result);
+        });
+}
+
+/// What a flood fill of row-major RGBA `pixels` paints: its color where the
+/// region is and transparent elsewhere, to be drawn over the same pixels.
+pub fn fill_patch(pixels: &[u8], width: usize, height: usize, fill: &FloodFill) -> PixelPatch {
+    let color = rgba(fill.color);
+    PixelPatch {
+        x: 0,
+        y: 0,
+        width: width as u32,
+        pixels: fill_region(pixels, width, height, fill)
+            .into_iter()
+            .flat_map(|filled| if filled { color } else { TRANSPARENT })
+            .collect(),
+    }
+}
+
+/// Blends `source` over `destination`, both row-major RGBA of the same size,
+/// with the source faded to `opacity` first.
+pub fn composite(destination: &mut [u8], source: &[u8], opacity: f64) {
+    destination
+        .chunks_exact_mut(4)
+        .zip(source.chunks_exact(4))
+        .for_each(|(pixel, source)| {
+            let faded = (source[3] as f64 * opacity).round() as u8;
+            let result = source_over(pixel, [source[0], source[1], source[2], faded]);
+            pixel.copy_from_slice(&result);
+        });
+}
+
+/// Which pixels a flood fill would paint, one flag per pixel in row-major order.
+pub fn fill_region(pixels: &[u8], width: usize, height: usize, fill: &FloodFill) -> Vec<bool> {
+    let mut region = vec![false; width * height];
+    let (x, y) = (fill.point.x.floor(), fill.point.y.floor());
+    if x < 0.0 || y < 0.0 || x >= width as f64 || y >= height as f64 {
+        return region;
+    }
+
+    let seed = y as usize * width + x as usize;
+    let start = &pixels[seed * 4..seed * 4 + 4];
+    let matches = |i: usize| {
+        pixels[i * 4..i * 4 + 4]
+            .iter()
+            .zip(start)
+            .all(|(channel, start)| channel.abs_diff(*start) <= fill.tolerance)
+    };
+    let neighbours: &[(isize, isize)] = match fill.connectivity {
+        Connectivity::Four => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
+        Connectivity::Eight => &[
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ],
+    };
+
+    region[seed] = true;
+    let mut stack = vec![seed];
+    while let Some(i) = stack.pop() {
+        let (x, y) = ((i % width) as isize, (i / width) as isize);
+        for (dx, dy) in neighbours {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize {
+                continue;
+            }
+            let neighbour = ny as usize * width + nx as usize;
+            if !region[neighbour] && matches(neighbour) {
+                region[neighbour] = true;
+                stack.push(neighbour);
+            }
+        }
+    }
+    region
+}
+
+// PRIVATE
+
+/// Which pixels a single primitive touches. Kept separate from the pixels so
+/// overlapping parts of one shape are only painted once. Only the touched
+/// pixels are listed, so a small shape stays cheap on a large canvas.
+struct Coverage {
+    width: usize,
+    height: usize,
+    covered: Vec<usize>,
+}
+
+impl Coverage {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            covered: vec![],
+        }
+    }
+
+    /// Marks every pixel whose center lies in `min..=max` and satisfies `inside`.
+    fn mark(
+        &mut self,
+        min: ElementPoint,
+        max: ElementPoint,
+        inside: impl Fn(ElementPoint) -> bool,
+    ) {
+        let x_range = pixel_range(min.x, max.x, self.width);
+        let y_range = pixel_range(min.y, max.y, self.height);
+        for y in y_range {
+            for x in x_range.clone() {
+                let center = ElementPoint::new(x as f64 + 0.5, y as f64 + 0.5);
+                if inside(center) {
+                    self.covered.push(y * self.width + x);
+                }
+            }
+        }
+    }
+
+    /// A butt-capped segment of half-width `half`.
+    fn segment(&mut self, start: ElementPoint, end: ElementPoint, half: f64) {
+        let direction = end - start;
+        let length = direction.length();
+        if length == 0.0 {
+            return;
+        }
+        let (min, max) = corners(start, end);
+        self.mark(
+            ElementPoint::new(min.x - half, min.y - half),
+            ElementPoint::new(max.x + half, max.y + half),
+            |p| {
+                let offset = p - start;
+                let along = offset.dot(direction) / length;
+                let across = offset.cross(direction).abs() / length;
+                (0.0..=length).contains(&along) && across <= half
+            },
+        );
+    }
+
+    fn disc(&mut self, center: ElementPoint, radius: f64) {
+        self.mark(
+            ElementPoint::new(center.x - radius, center.y - radius),
+            ElementPoint::new(center.x + radius, center.y + radius),
+            |p| (p - center).length() <= radius,
+        );
+    }
+
+    fn covered_pixels(mut self) -> impl Iterator<Item = (usize, usize)> {
+        self.covered.sort_unstable();
+        self.covered.dedup();
+        let width = self.width;
+        self.covered
+            .into_iter()
+            .map(move |i| (i % width, i / width))
+    }
+}
+
+/// Pixels whose centers can fall between `min` and `max`, clipped to `0..limit`.
+fn pixel_range(min: f64, max: f64, limit: usize) -> std::ops::Range<usize> {
+    let start = (min - 0.5).ceil().max(0.0) as usize;
+    let end = ((max - 0.5).floor() + 1.0).clamp(0.0, limit as f64) as usize;
+    start.min(end)..end
+}
+
+fn corners(a: ElementPoint, b: ElementPoint) -> (ElementPoint, ElementPoint) {
+    (
+        ElementPoint::new(a.x.min(b.x), a.y.min(b.y)),
+        ElementPoint::new(a.x.max(b.x), a.y.max(b.y)),
+    )
+}
+
+fn bounds(points: &[ElementPoint]) -> Option<(ElementPoint, ElementPoint)> {
+    let first = *points.first()?;
+    Some(points.iter().fold((first, first), |(min, max), point| {
+        (min.min(*point), max.max(*point))
+    }))
+}
+
+fn rgba(color: Color) -> [u8; 4] {
+    let channel = |value: f64| value.round().clamp(0.0, 255.0) as u8;
+    [
+        channel(color.0),
+        channel(color.1),
+        channel(color.2),
+        channel(color.3 * 255.0),
+    ]
+}
+
+// Pixels are stored unpremultiplied, like `ImageData`, so blending goes
+// through premultiplied values and back.
+
+fn source_over(destination: &[u8], source: [u8; 4]) -> [u8; 4] {
+    let source_alpha = source[3] as f64 / 255.0;
+    let destination_alpha = destination[3] as f64 / 255.0 * (1.0 - source_alpha);
+    let alpha = source_alpha + destination_alpha;
+    if alpha == 0.0 {
+        return TRANSPARENT;
+    }
+    let channel = |i: usize| {
+        let value =
+            (source[i] as f64 * source_alpha + destination[i] as f64 * destination_alpha) / alpha;
+        value.round() as u8
+    };
+    [
+        channel(0),
+        channel(1),
+        channel(2),
+        (alpha * 255.0).round() as u8,
+    ]
+}
+
+fn destination_out(destination: &[u8], source: [u8; 4]) -> [u8; 4] {
+    let alpha = destination[3] as f64 * (1.0 - source[3] as f64 / 255.0);
+    match alpha.round() as u8 {
+        0 => TRANSPARENT,
+        alpha => [destination[0], destination[1], destination[2], alpha],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drawing::{FillMode, Path, Polygon, RasterPatch, ShapeProperties, Transformation};
+    use crate::layers::{layer_commands, LayerCommand};
+
+    const RED: Color = Color(255.0, 0.0, 0.0, 1.0);
+    const BLUE: Color = Color(0.0, 0.0, 255.0, 1.0);
+    const GREEN: Color = Color(0.0, 255.0, 0.0, 1.0);
+
+    /// Pixels drawn as text, a character per pixel: `.` transparent, `R`, `G`
+    /// and `B` opaque red, green and blue.
+    fn pixels(rows: &[&str]) -> Vec<u8> {
+        rows.iter()
+            .flat_map(|row| row.chars())
+            .flat_map(|pixel| match pixel {
+                '.' => TRANSPARENT,
+                'R' => [255, 0, 0, 255],
+                'G' => [0, 255, 0, 255],
+                'B' => [0, 0, 255, 255],
+                other => panic!("no pixel for {:?}", other),
+            })
+            .collect()
+    }
+
+    /// The other way around, so a failure shows what was drawn.
+    fn rows(raster: &Raster) -> Vec<String> {
+        raster
+            .pixels()
+            .chunks_exact(4)
+            .map(|pixel| match pixel {
+                [_, _, _, 0] => '.',
+                [255, 0, 0, 255] => 'R',
+                [0, 255, 0, 255] => 'G',
+                [0, 0, 255, 255] => 'B',
+                _ => '?',
+            })
+            .collect::<Vec<char>>()
+            .chunks(raster.width)
+            .map(|row| row.iter().collect())
+            .collect()
+    }
+
+    fn assert_renders(commands: &[Command], expected: &[&str]) {
+        let (width, height) = (expected[0].len(), expected.len());
+        let raster = Raster::from_commands(width, height, commands);
+        assert_eq!(
+            raster.pixels(),
+            pixels(expected).as_slice(),
+            "drew {:#?}",
+            rows(&raster)
+        );
+    }
+
+    /// Like `assert_renders`, after resolving deletions and transformations
+    /// the way a layer's history is replayed.
+    fn assert_renders_history(commands: &[Command], expected: &[&str]) {
+        let history: Vec<LayerCommand> = commands
+            .iter()
+            .map(|command| LayerCommand {
+                layer: 0,
+                command: command.clone(),
+            })
+            .collect();
+        assert_renders(&layer_commands(&history, 0), expected);
+    }
+
+    fn point(x: f64, y: f64) -> ElementPoint {
+        ElementPoint::new(x, y)
+    }
+
+    fn shape(
+        start: ElementPoint,
+        end: ElementPoint,
+        line_width: f64,
+        fill_mode: FillMode,
+    ) -> ShapeProperties {
+        ShapeProperties {
+            start_point: start,
+            end_point: end,
+            color: RED,
+            line_width,
+            fill_color: BLUE,
+            fill_mode,
+        }
+    }
+
+    #[test]
+    fn draw_path_joins_its_segments_with_butt_ends() {
+        let path = Command::DrawPath(Path {
+            points: vec![point(1.0, 1.0), point(5.0, 1.0), point(5.0, 5.0)],
+            color: RED,
+            line_width: 2.0,
+        });
+        #[rustfmt::skip]
+        let expected = [
+            ".RRRRR.",
+            ".RRRRR.",
+            "....RR.",
+            "....RR.",
+            "....RR.",
+            ".......",
+            ".......",
+        ];
+        assert_renders(&[path], &expected);
+    }
+
+    #[test]
+    fn erase_path_clears_a_round_brush() {
+        let fill =
+            Command::DrawRectangle(shape(point(0.0, 0.0), point(7.0, 5.0), 1.0, FillMode::Fill));
+        let erase = Command::ErasePath(Path {
+            points: vec![point(0.0, 2.5), point(7.0, 2.5)],
+            color: RED,
+            line_width: 1.0,
+        });
+        #[rustfmt::skip]
+        let expected = [
+            "BBBBBBB",
+            "BBBBBBB",
+            ".......",
+            "BBBBBBB",
+            "BBBBBBB",
+        ];
+        assert_renders(&[fill, erase], &expected);
+    }
+
+    #[test]
+    fn draw_line_has_butt_caps() {
+        let line = Command::DrawLine(shape(
+            point(1.0, 2.0),
+            point(7.0, 2.0),
+            2.0,
+            FillMode::Stroke,
+        ));
+        #[rustfmt::skip]
+        let expected = [
+            "........",
+            ".RRRRRR.",
+            ".RRRRRR.",
+            "........",
+        ];
+        assert_renders(&[line], &expected);
+    }
+
+    #[test]
+    fn draw_circle_strokes_over_its_fill() {
+        let circle = Command::DrawCircle(shape(
+            point(3.5, 3.5),
+            point(3.5, 1.0),
+            1.0,
+            FillMode::StrokeAndFill,
+        ));
+        #[rustfmt::skip]
+        let expected = [
+            "...R...",
+            ".RRRRR.",
+            ".RBBBR.",
+            "RRBBBRR",
+            ".RBBBR.",
+            ".RRRRR.",
+            "...R...",
+        ];
+        assert_renders(&[circle], &expected);
+    }
+
+    #[test]
+    fn draw_rectangle_strokes_over_its_fill() {
+        let rectangle = Command::DrawRectangle(shape(
+            point(1.0, 1.0),
+            point(7.0, 5.0),
+            2.0,
+            FillMode::StrokeAndFill,
+        ));
+        #[rustfmt::skip]
+        let expected = [
+            "RRRRRRRR",
+            "RRRRRRRR",
+            "RRBBBBRR",
+            "RRBBBBRR",
+            "RRRRRRRR",
+            "RRRRRRRR",
+        ];
+        assert_renders(&[rectangle], &expected);
+    }
+
+    #[test]
+    fn draw_polygon_fills_inside_the_outline() {
+        let polygon = Command::DrawPolygon(Polygon {
+            points: vec![point(0.0, 0.0), point(6.0, 0.0), point(0.0, 6.0)],
+            color: RED,
+            line_width: 1.0,
+            fill_color: BLUE,
+            fill_mode: FillMode::Fill,
+        });
+        #[rustfmt::skip]
+        let expected = [
+            "BBBBB.",
+            "BBBB..",
+            "BBB...",
+            "BB....",
+            "B.....",
+            "......",
+        ];
+        assert_renders(&[polygon], &expected);
+    }
+
+    #[test]
+    fn flood_fill_stays_inside_the_outline() {
+        let outline = Command::DrawRectangle(shape(
+            point(1.0, 1.0),
+            point(6.0, 6.0),
+            2.0,
+            FillMode::Stroke,
+        ));
+        let fill = |x: f64, y: f64| {
+            Command::FloodFill(FloodFill {
+                point: point(x, y),
+                color: GREEN,
+                tolerance: 0,
+                connectivity: Connectivity::Four,
+            })
+        };
+        #[rustfmt::skip]
+        let expected = [
+            "RRRRRRR",
+            "RRRRRRR",
+            "RRGGGRR",
+            "RRGGGRR",
+            "RRGGGRR",
+            "RRRRRRR",
+            "RRRRRRR",
+        ];
+        assert_renders(&[outline, fill(3.5, 3.5)], &expected);
+    }
+
+    #[test]
+    fn fill_patch_paints_the_region_over_the_pixels_it_was_worked_out_on() {
+        let outline = Command::DrawRectangle(shape(
+            point(1.0, 1.0),
+            point(6.0, 6.0),
+            2.0,
+            FillMode::Stroke,
+        ));
+        let fill = FloodFill {
+            point: point(3.5, 3.5),
+            color: GREEN,
+            tolerance: 0,
+            connectivity: Connectivity::Four,
+        };
+        let mut raster = Raster::from_commands(7, 7, std::slice::from_ref(&outline));
+        let patch = fill_patch(raster.pixels(), 7, 7, &fill);
+        #[rustfmt::skip]
+        let expected = [
+            ".......",
+            ".......",
+            "..GGG..",
+            "..GGG..",
+            "..GGG..",
+            ".......",
+            ".......",
+        ];
+        assert_eq!(patch.pixels, pixels(&expected));
+
+        // which is what filling does
+        raster.paste(&patch);
+        assert_eq!(
+            raster,
+            Raster::from_commands(7, 7, &[outline, Command::FloodFill(fill)])
+        );
+    }
+
+    #[test]
+    fn erase_canvas_clears_everything_before_it() {
+        let line =
+            |y: f64| Command::DrawLine(shape(point(0.0, y), point(4.0, y), 1.0, FillMode::Stroke));
+        #[rustfmt::skip]
+        let expected = [
+            "....",
+            "....",
+            "RRRR",
+        ];
+        assert_renders(&[line(0.5), Command::EraseCanvas, line(2.5)], &expected);
+    }
+
+    #[test]
+    fn patch_clears_its_outline_then_pastes() {
+        let fill =
+            Command::DrawRectangle(shape(point(0.0, 0.0), point(6.0, 4.0), 1.0, FillMode::Fill));
+        let patch = Command::Patch(RasterPatch {
+            cleared: vec![
+                point(1.0, 1.0),
+                point(3.0, 1.0),
+                point(3.0, 3.0),
+                point(1.0, 3.0),
+            ],
+            pasted: Some(PixelPatch {
+                x: 4,
+                y: 1,
+                width: 1,
+                pixels: pixels(&["G", "R"]),
+            }),
+        });
+        #[rustfmt::skip]
+        let expected = [
+            "BBBBBB",
+            "B..BGB",
+            "B..BRB",
+            "BBBBBB",
+        ];
+        assert_renders(&[fill, patch], &expected);
+    }
+
+    #[test]
+    fn group_draws_its_commands_in_order() {
+        let line =
+            |y: f64| Command::DrawLine(shape(point(0.0, y), point(4.0, y), 1.0, FillMode::Stroke));
+        let fill = Command::FloodFill(FloodFill {
+            point: point(0.5, 1.5),
+            color: GREEN,
+            tolerance: 0,
+            connectivity: Connectivity::Four,
+        });
+        #[rustfmt::skip]
+        let expected = [
+            "RRRR",
+            "GGGG",
+            "RRRR",
+            "....",
+        ];
+        assert_renders(
+            &[Command::group(vec![line(0.5), line(2.5), fill])],
+            &expected,
+        );
+    }
+
+    #[test]
+    fn flattened_pastes_its_pixels_over_a_blank_layer() {
+        let flattened = Command::Flattened(PixelPatch {
+            x: 0,
+            y: 0,
+            width: 3,
+            pixels: pixels(&["R.G", ".B."]),
+        });
+        #[rustfmt::skip]
+        let expected = [
+            "R.G",
+            ".B.",
+            "...",
+        ];
+        assert_renders(&[flattened], &expected);
+    }
+
+    #[test]
+    fn delete_leaves_out_its_target() {
+        let line =
+            |y: f64| Command::DrawLine(shape(point(0.0, y), point(4.0, y), 1.0, FillMode::Stroke));
+        #[rustfmt::skip]
+        let expected = [
+            "RRRR",
+            "....",
+            "RRRR",
+        ];
+        assert_renders_history(
+            &[line(0.5), line(1.5), line(2.5), Command::Delete(1)],
+            &expected,
+        );
+    }
+
+    #[test]
+    fn transform_moves_its_targets() {
+        let line =
+            |y: f64| Command::DrawLine(shape(point(0.0, y), point(2.0, y), 1.0, FillMode::Stroke));
+        let transform = Command::Transform(Transformation {
+            targets: vec![1],
+            center: point(1.0, 1.5),
+            scale: 1.0,
+            rotation: 0.0,
+            offset: euclid::vec2(2.0, 1.0),
+        });
+        #[rustfmt::skip]
+        let expected = [
+            "RR..",
+            "....",
+            "..RR",
+        ];
+        assert_renders_history(&[line(0.5), line(1.5), transform], &expected);
+    }
+}
@@ -0,0 +1,1402 @@
+// svg.rs
+//
+// Vector export and import of the command history. On export, strokes map onto
+// plain SVG elements and erasing is expressed with masks, so that an eraser
+// stroke only hides what was drawn before it, the same as on the canvas.
+// Flood fills have no vector equivalent, so their region is worked out on a
+// `Raster` and written as pixel runs, and pasted pixels are written the same way.
+// On import, basic shapes become their matching commands and everything else
+// with an outline is flattened into pen paths, or polygons when closed.
+
+use crate::drawing::{
+    Color, Command, FillMode, Path, PixelPatch, Polygon, RenderTarget, ShapeProperties,
+};
+use crate::layers::{layer_commands, Layer, LayerCommand};
+use crate::raster::{fill_region, Raster};
+use dioxus::prelude::*;
+use dioxus_elements::geometry::{ElementPoint, ElementSpace};
+use roxmltree::Node;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+use svgtypes::{Length, Paint, PointsParser, SimplePathSegment, SimplifyingPathParser};
+
+/// Visible layers become groups, bottom to top, labelled so vector editors
+/// pick them up as layers too.
+pub fn export_svg(layers: &[Layer], history: &[LayerCommand], width: f64, height: f64) -> String {
+    let mut defs = String::new();
+    let mut body = String::new();
+    let mut mask_count = 0;
+
+    for layer in layers.iter().filter(|layer| layer.visible) {
+        let commands = layer_commands(history, layer.id);
+        writeln!(
+            body,
+            r#"<g inkscape:groupmode="layer" inkscape:label="{}" opacity="{}">"#,
+            escape(&layer.name),
+            layer.opacity
+        )
+        .unwrap();
+        body.push_str(&layer_elements(
+            &commands,
+            width,
+            height,
+            &mut defs,
+            &mut mask_count,
+        ));
+        body.push_str("</g>\n");
+    }
+
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" "#,
+            r#"width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            "\n<defs>\n{defs}</defs>\n{body}</svg>\n"
+        ),
+        w = width,
+        h = height,
+        defs = defs,
+        body = body,
+    )
+}
+
+/// The commands read from an SVG file, plus one line for every element that
+/// was skipped or could only be brought over approximately.
+#[derive(Debug, Default)]
+pub struct SvgImport {
+    pub commands: Vec<Command>,
+    pub report: Vec<String>,
+}
+
+impl SvgImport {
+    pub fn summary(&self) -> String {
+        let mut summary = format!("Imported {} shapes.", self.commands.len());
+        if !self.report.is_empty() {
+            summary.push_str("\n\nSkipped or approximated:");
+            self.report
+                .iter()
+                .for_each(|line| write!(summary, "\n- {}", line).unwrap());
+        }
+        summary
+    }
+
+    fn note(&mut self, node: Node, message: &str) {
+        let position = node.document().text_pos_at(node.range().start);
+        self.report.push(format!(
+            "line {}: <{}> {}",
+            position.row,
+            node.tag_name().name(),
+            message
+        ));
+    }
+}
+
+pub fn import_svg(svg: &str) -> Result<SvgImport, roxmltree::Error> {
+    let document = roxmltree::Document::parse(svg)?;
+    let mut import = SvgImport::default();
+    import_element(document.root_element(), Style::default(), &mut import);
+    Ok(import)
+}
+
+// PRIVATE
+
+/// The elements for one layer's commands. Erase masks are added to `defs`,
+/// numbered on from `mask_count`.
+fn layer_elements(
+    commands: &[Command],
+    width: f64,
+    height: f64,
+    defs: &mut String,
+    mask_count: &mut usize,
+) -> String {
+    let mut body = String::new();
+    // the layer drawn up to the last fill, carried on to the next one so
+    // every command is rasterized once however many fills there are
+    let mut drawn: Option<(Raster, usize)> = None;
+
+    let commands = effective_commands(commands);
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            Command::DrawPath(path) => {
+                if path.points.len() > 1 {
+                    writeln!(
+                        body,
+                        r#"<path d="{}" fill="none"{}/>"#,
+                        path_data(&path.points),
+                        stroke_attributes(path.color, path.line_width)
+                    )
+                    .unwrap();
+                }
+            }
+            Command::DrawLine(shape_properties) => {
+                let (start, end) = (shape_properties.start_point, shape_properties.end_point);
+                writeln!(
+                    body,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}"{}/>"#,
+                    start.x,
+                    start.y,
+                    end.x,
+                    end.y,
+                    stroke_attributes(shape_properties.color, shape_properties.line_width)
+                )
+                .unwrap();
+            }
+            Command::DrawCircle(shape_properties) => {
+                let (center, edge) = (shape_properties.start_point, shape_properties.end_point);
+                writeln!(
+                    body,
+                    r#"<circle cx="{}" cy="{}" r="{}"{}/>"#,
+                    center.x,
+                    center.y,
+                    (edge - center).length(),
+                    paint_attributes(
+                        shape_properties.color,
+                        shape_properties.line_width,
+                        shape_properties.fill_color,
+                        shape_properties.fill_mode
+                    )
+                )
+                .unwrap();
+            }
+            Command::DrawRectangle(shape_properties) => {
+                let (start, end) = (shape_properties.start_point, shape_properties.end_point);
+                writeln!(
+                    body,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}"{}/>"#,
+                    start.x.min(end.x),
+                    start.y.min(end.y),
+                    (end.x - start.x).abs(),
+                    (end.y - start.y).abs(),
+                    paint_attributes(
+                        shape_properties.color,
+                        shape_properties.line_width,
+                        shape_properties.fill_color,
+                        shape_properties.fill_mode
+                    )
+                )
+                .unwrap();
+            }
+            Command::DrawPolygon(polygon) => {
+                writeln!(
+                    body,
+                    r#"<polygon points="{}"{}/>"#,
+                    polygon
+                        .points
+                        .iter()
+                        .map(|point| format!("{},{}", point.x, point.y))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    paint_attributes(
+                        polygon.color,
+                        polygon.line_width,
+                        polygon.fill_color,
+                        polygon.fill_mode
+                    )
+                )
+                .unwrap();
+            }
+            Command::FloodFill(flood_fill) => {
+                // the raster has no anti-aliasing, so edges can differ by a pixel from the canvas
+                let (w, h) = (width as usize, height as usize);
+                let (raster, up_to) = drawn.get_or_insert_with(|| {
+                    (Raster::from_commands(w, h, &commands[..index]), index)
+                });
+                raster.execute_commands(&commands[*up_to..index]);
+                *up_to = index;
+                let region = fill_region(raster.pixels(), w, h, flood_fill);
+                if region.contains(&true) {
+                    writeln!(
+                        body,
+                        r#"<path d="{}"{} shape-rendering="crispEdges"/>"#,
+                        region_data(&region, w),
+                        fill_attributes(flood_fill.color)
+                    )
+                    .unwrap();
+                }
+            }
+            Command::ErasePath(path) => {
+                *mask_count += 1;
+                let id = format!("erase-{}", mask_count);
+                writeln!(defs, "{}", erase_mask(&id, path, width, height)).unwrap();
+                body = format!("<g mask=\"url(#{})\">\n{}</g>\n", id, body);
+            }
+            Command::Patch(patch) => {
+                if !patch.cleared.is_empty() {
+                    *mask_count += 1;
+                    let id = format!("erase-{}", mask_count);
+                    writeln!(defs, "{}", clear_mask(&id, &patch.cleared, width, height)).unwrap();
+                    body = format!("<g mask=\"url(#{})\">\n{}</g>\n", id, body);
+                }
+                if let Some(pasted) = &patch.pasted {
+                    body.push_str(&patch_elements(pasted));
+                }
+            }
+            Command::Flattened(pixels) => body.push_str(&patch_elements(pixels)),
+            // effective_commands already starts after the last clear, and
+            // deletions, transformations and groups are resolved before it
+            Command::EraseCanvas
+            | Command::Delete(_)
+            | Command::Transform(_)
+            | Command::Group(_) => {}
+        }
+    }
+
+    body
+}
+
+/// Everything drawn since the canvas was last cleared. Anything before that
+/// can't be visible, so it is left out rather than masked.
+fn effective_commands(commands: &[Command]) -> &[Command] {
+    match commands
+        .iter()
+        .rposition(|command| *command == Command::EraseCanvas)
+    {
+        Some(clear) => &commands[clear + 1..],
+        None => commands,
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn stroke_attributes(color: Color, line_width: f64) -> String {
+    format!(
+        r#" stroke="{}"{} stroke-width="{}""#,
+        svg_color(color),
+        opacity_attribute("stroke-opacity", color),
+        line_width
+    )
+}
+
+fn fill_attributes(color: Color) -> String {
+    format!(
+        r#" fill="{}"{}"#,
+        svg_color(color),
+        opacity_attribute("fill-opacity", color)
+    )
+}
+
+/// SVG 1.1 colors have no alpha; it goes in a separate opacity attribute instead.
+fn svg_color(color: Color) -> String {
+    format!("rgb({},{},{})", color.0, color.1, color.2)
+}
+
+fn opacity_attribute(name: &str, color: Color) -> String {
+    match color.3 < 1.0 {
+        true => format!(r#" {}="{}""#, name, color.3),
+        false => String::new(),
+    }
+}
+
+/// Fill and stroke of a closed shape.
+fn paint_attributes(
+    color: Color,
+    line_width: f64,
+    fill_color: Color,
+    fill_mode: FillMode,
+) -> String {
+    let fill = match fill_mode.fills() {
+        true => fill_attributes(fill_color),
+        false => r#" fill="none""#.to_string(),
+    };
+    match fill_mode.strokes() {
+        true => fill + &stroke_attributes(color, line_width),
+        false => fill + r#" stroke="none""#,
+    }
+}
+
+fn path_data(points: &[ElementPoint]) -> String {
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let op = if i == 0 { 'M' } else { 'L' };
+            format!("{}{} {}", op, point.x, point.y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// One rectangle per horizontal run of filled pixels.
+fn region_data(region: &[bool], width: usize) -> String {
+    let mut runs = vec![];
+    for (y, row) in region.chunks(width).enumerate() {
+        let mut x = 0;
+        while x < width {
+            if !row[x] {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < width && row[x] {
+                x += 1;
+            }
+            runs.push(format!("M{} {}h{}v1h-{}z", start, y, x - start, x - start));
+        }
+    }
+    runs.join(" ")
+}
+
+/// The eraser is a round brush, so its path is stroked with round caps and
+/// joins; a lone point is given a zero length segment, which a round cap
+/// turns into a dot.
+fn erase_mask(id: &str, path: &Path, width: f64, height: f64) -> String {
+    let mut points = path.points.clone();
+    if points.len() == 1 {
+        points.push(points[0]);
+    }
+    let hidden = format!(
+        r#"<path d="{}" fill="none" stroke="black" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+        path_data(&points),
+        path.line_width
+    );
+    mask(id, &hidden, width, height)
+}
+
+/// Hides what a raster selection cleared.
+fn clear_mask(id: &str, outline: &[ElementPoint], width: f64, height: f64) -> String {
+    let hidden = format!(r#"<path d="{} Z" fill="black"/>"#, path_data(outline));
+    mask(id, &hidden, width, height)
+}
+
+/// White keeps what is underneath, black hides it.
+fn mask(id: &str, hidden: &str, width: f64, height: f64) -> String {
+    format!(
+        concat!(
+            r#"<mask id="{}" maskUnits="userSpaceOnUse" x="0" y="0" width="{w}" height="{h}">"#,
+            r#"<rect width="{w}" height="{h}" fill="white"/>{}</mask>"#
+        ),
+        id,
+        hidden,
+        w = width,
+        h = height,
+    )
+}
+
+/// Pasted pixels as one path per color, each a rectangle per horizontal run
+/// of that color, like flood fills.
+fn patch_elements(patch: &PixelPatch) -> String {
+    let mut runs: BTreeMap<&[u8], Vec<String>> = BTreeMap::new();
+    for (y, row) in patch
+        .pixels
+        .chunks_exact(patch.width as usize * 4)
+        .enumerate()
+    {
+        let pixels: Vec<&[u8]> = row.chunks_exact(4).collect();
+        let mut x = 0;
+        while x < pixels.len() {
+            let start = x;
+            while x < pixels.len() && pixels[x] == pixels[start] {
+                x += 1;
+            }
+            if pixels[start][3] > 0 {
+                let (left, top, length) = (patch.x + start as i32, patch.y + y as i32, x - start);
+                runs.entry(pixels[start])
+                    .or_default()
+                    .push(format!("M{} {}h{}v1h-{}z", left, top, length, length));
+            }
+        }
+    }
+    runs.into_iter()
+        .map(|(rgba, runs)| {
+            let color = Color(
+                rgba[0] as f64,
+                rgba[1] as f64,
+                rgba[2] as f64,
+                rgba[3] as f64 / 255.0,
+            );
+            format!(
+                "<path d=\"{}\"{} shape-rendering=\"crispEdges\"/>\n",
+                runs.join(" "),
+                fill_attributes(color)
+            )
+        })
+        .collect()
+}
+
+// IMPORT
+
+type Transform = euclid::Transform2D<f64, ElementSpace, ElementSpace>;
+
+/// Presentation attributes as inherited down the element tree.
+#[derive(Clone, Copy)]
+struct Style {
+    stroke: Option<Color>,
+    fill: Option<Color>,
+    stroke_width: f64,
+    stroke_opacity: f64,
+    fill_opacity: f64,
+    current_color: Color,
+    transform: Transform,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            stroke: None,
+            fill: Some(Color(0.0, 0.0, 0.0, 1.0)),
+            stroke_width: 1.0,
+            stroke_opacity: 1.0,
+            fill_opacity: 1.0,
+            current_color: Color(0.0, 0.0, 0.0, 1.0),
+            transform: Transform::identity(),
+        }
+    }
+}
+
+impl Style {
+    fn cascade(mut self, node: Node, import: &mut SvgImport) -> Self {
+        if let Some(color) =
+            property(node, "color").and_then(|value| svgtypes::Color::from_str(value).ok())
+        {
+            self.current_color = color_from_svg(color);
+        }
+        if let Some(value) = property(node, "stroke") {
+            self.stroke = self.paint(value, self.stroke, node, import);
+        }
+        if let Some(value) = property(node, "fill") {
+            self.fill = self.paint(value, self.fill, node, import);
+        }
+        if let Some(width) = property(node, "stroke-width").and_then(length) {
+            self.stroke_width = width;
+        }
+        if let Some(opacity) = property(node, "stroke-opacity").and_then(opacity) {
+            self.stroke_opacity = opacity;
+        }
+        if let Some(opacity) = property(node, "fill-opacity").and_then(opacity) {
+            self.fill_opacity = opacity;
+        }
+        if let Some(value) = node.attribute("transform") {
+            match svgtypes::Transform::from_str(value) {
+                Ok(t) => {
+                    let local = Transform::new(t.a, t.b, t.c, t.d, t.e, t.f);
+                    self.transform = local.then(&self.transform);
+                }
+                Err(_) => import.note(node, "has an unreadable transform, ignored it"),
+            }
+        }
+        self
+    }
+
+    fn paint(
+        &self,
+        value: &str,
+        inherited: Option<Color>,
+        node: Node,
+        import: &mut SvgImport,
+    ) -> Option<Color> {
+        match Paint::from_str(value) {
+            Ok(Paint::None) => None,
+            Ok(Paint::CurrentColor) => Some(self.current_color),
+            Ok(Paint::Color(color)) => Some(color_from_svg(color)),
+            Ok(Paint::FuncIRI(_, fallback)) => {
+                import.note(
+                    node,
+                    "uses a gradient or pattern, replaced it with a solid color",
+                );
+                match fallback {
+                    Some(svgtypes::PaintFallback::Color(color)) => Some(color_from_svg(color)),
+                    _ => Some(self.current_color),
+                }
+            }
+            Ok(_) => inherited,
+            Err(_) => {
+                import.note(
+                    node,
+                    &format!("has an unreadable paint '{}', ignored it", value),
+                );
+                inherited
+            }
+        }
+    }
+
+    fn stroke_color(&self) -> Option<Color> {
+        self.stroke
+            .map(|color| color.with_alpha(color.3 * self.stroke_opacity))
+    }
+
+    fn fill_color(&self) -> Option<Color> {
+        self.fill
+            .map(|color| color.with_alpha(color.3 * self.fill_opacity))
+    }
+
+    /// The color and width an open shape's outline is drawn with. Only closed
+    /// shapes can be filled, so a fill-only one keeps its shape as an outline in
+    /// its fill color.
+    fn outline(&self, node: Node, import: &mut SvgImport) -> Option<(Color, f64)> {
+        let scale = self.transform.determinant().abs().sqrt();
+        match (self.stroke_color(), self.fill_color()) {
+            (Some(stroke), _) => Some((stroke, self.stroke_width * scale)),
+            (None, Some(fill)) => {
+                import.note(node, "is filled, imported its outline only");
+                Some((fill, self.stroke_width * scale))
+            }
+            (None, None) => {
+                import.note(node, "has neither stroke nor fill, skipped it");
+                None
+            }
+        }
+    }
+
+    /// How a closed shape is painted, or `None` when it would be invisible.
+    fn closed_paint(&self, node: Node, import: &mut SvgImport) -> Option<ClosedPaint> {
+        let line_width = self.stroke_width * self.transform.determinant().abs().sqrt();
+        let (color, fill_mode) = match (self.stroke_color(), self.fill_color()) {
+            (Some(stroke), Some(_)) => (stroke, FillMode::StrokeAndFill),
+            (Some(stroke), None) => (stroke, FillMode::Stroke),
+            (None, Some(fill)) => (fill, FillMode::Fill),
+            (None, None) => {
+                import.note(node, "has neither stroke nor fill, skipped it");
+                return None;
+            }
+        };
+        Some(ClosedPaint {
+            color,
+            line_width,
+            fill_color: self.fill_color().unwrap_or(Color(255.0, 255.0, 255.0, 1.0)),
+            fill_mode,
+        })
+    }
+
+    fn is_axis_aligned(&self) -> bool {
+        self.transform.m12 == 0.0 && self.transform.m21 == 0.0
+    }
+
+    /// Rotation and uniform scale only, which keeps circles circular.
+    fn is_similarity(&self) -> bool {
+        let t = &self.transform;
+        (t.m11 - t.m22).abs() < 1e-9 && (t.m12 + t.m21).abs() < 1e-9
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ClosedPaint {
+    color: Color,
+    line_width: f64,
+    fill_color: Color,
+    fill_mode: FillMode,
+}
+
+fn import_element(node: Node, inherited: Style, import: &mut SvgImport) {
+    let style = inherited.cascade(node, import);
+    match node.tag_name().name() {
+        "svg" => {
+            let style = Style {
+                transform: view_box(node).then(&style.transform),
+                ..style
+            };
+            import_children(node, style, import);
+        }
+        "g" | "a" | "switch" => import_children(node, style, import),
+        "line" => import_line(node, style, import),
+        "rect" => import_rect(node, style, import),
+        "circle" => {
+            let radius = number(node, "r");
+            import_ellipse(node, style, radius, radius, import);
+        }
+        "ellipse" => import_ellipse(node, style, number(node, "rx"), number(node, "ry"), import),
+        "polyline" | "polygon" => import_polyline(node, style, import),
+        "path" => import_path(node, style, import),
+        // definitions and metadata aren't drawn where they appear
+        "defs" | "title" | "desc" | "metadata" | "style" | "symbol" | "clipPath" | "mask"
+        | "linearGradient" | "radialGradient" | "pattern" | "marker" | "filter" => {}
+        _ => import.note(node, "is not supported, skipped it"),
+    }
+}
+
+fn import_children(node: Node, style: Style, import: &mut SvgImport) {
+    node.children()
+        .filter(Node::is_element)
+        .for_each(|child| import_element(child, style, import));
+}
+
+fn import_line(node: Node, style: Style, import: &mut SvgImport) {
+    let Some((color, line_width)) = style.outline(node, import) else {
+        return;
+    };
+    let start = style.transform.transform_point(point(node, "x1", "y1"));
+    let end = style.transform.transform_point(point(node, "x2", "y2"));
+    import.commands.push(Command::DrawLine(ShapeProperties {
+        start_point: start,
+        end_point: end,
+        color,
+        line_width,
+        fill_color: Color(255.0, 255.0, 255.0, 1.0),
+        fill_mode: FillMode::Stroke,
+    }));
+}
+
+fn import_rect(node: Node, style: Style, import: &mut SvgImport) {
+    let (width, height) = (number(node, "width"), number(node, "height"));
+    if width <= 0.0 || height <= 0.0 {
+        import.note(node, "has no area, skipped it");
+        return;
+    }
+    let Some(paint) = style.closed_paint(node, import) else {
+        return;
+    };
+    if number(node, "rx") > 0.0 || number(node, "ry") > 0.0 {
+        import.note(node, "has rounded corners, imported them square");
+    }
+
+    let start = point(node, "x", "y");
+    let corners = [
+        start,
+        ElementPoint::new(start.x + width, start.y),
+        ElementPoint::new(start.x + width, start.y + height),
+        ElementPoint::new(start.x, start.y + height),
+    ]
+    .map(|corner| style.transform.transform_point(corner));
+
+    if style.is_axis_aligned() {
+        import
+            .commands
+            .push(Command::DrawRectangle(ShapeProperties {
+                start_point: corners[0],
+                end_point: corners[2],
+                color: paint.color,
+                line_width: paint.line_width,
+                fill_color: paint.fill_color,
+                fill_mode: paint.fill_mode,
+            }));
+    } else {
+        push_polygon(import, corners.to_vec(), paint);
+    }
+}
+
+fn import_ellipse(node: Node, style: Style, rx: f64, ry: f64, import: &mut SvgImport) {
+    if rx <= 0.0 || ry <= 0.0 {
+        import.note(node, "has no area, skipped it");
+        return;
+    }
+    let Some(paint) = style.closed_paint(node, import) else {
+        return;
+    };
+    let center = point(node, "cx", "cy");
+
+    if rx == ry && style.is_similarity() {
+        let edge = ElementPoint::new(center.x + rx, center.y);
+        import.commands.push(Command::DrawCircle(ShapeProperties {
+            start_point: style.transform.transform_point(center),
+            end_point: style.transform.transform_point(edge),
+            color: paint.color,
+            line_width: paint.line_width,
+            fill_color: paint.fill_color,
+            fill_mode: paint.fill_mode,
+        }));
+    } else {
+        let segments = flattening_segments(2.0 * std::f64::consts::PI * rx.max(ry));
+        let points = (0..segments)
+            .map(|i| {
+                let angle = 2.0 * std::f64::consts::PI * i as f64 / segments as f64;
+                let p = ElementPoint::new(center.x + rx * angle.cos(), center.y + ry * angle.sin());
+                style.transform.transform_point(p)
+            })
+            .collect();
+        push_polygon(import, points, paint);
+    }
+}
+
+fn import_polyline(node: Node, style: Style, import: &mut SvgImport) {
+    let points: Vec<ElementPoint> = PointsParser::from(node.attribute("points").unwrap_or(""))
+        .map(|(x, y)| style.transform.transform_point(ElementPoint::new(x, y)))
+        .collect();
+    if node.tag_name().name() == "polygon" {
+        if let Some(paint) = style.closed_paint(node, import) {
+            push_polygon(import, points, paint);
+        }
+    } else if let Some((color, line_width)) = style.outline(node, import) {
+        push_path(import, points, color, line_width);
+    }
+}
+
+fn import_path(node: Node, style: Style, import: &mut SvgImport) {
+    let Some(paint) = style.closed_paint(node, import) else {
+        return;
+    };
+
+    // each subpath and whether it was closed
+    let mut subpaths: Vec<(Vec<ElementPoint>, bool)> = vec![];
+    let mut current = ElementPoint::zero();
+    for segment in SimplifyingPathParser::from(node.attribute("d").unwrap_or("")) {
+        let segment = match segment {
+            Ok(segment) => segment,
+            Err(_) => {
+                import.note(node, "has malformed path data, imported it up to the error");
+                break;
+            }
+        };
+        match segment {
+            SimplePathSegment::MoveTo { x, y } => {
+                current = ElementPoint::new(x, y);
+                subpaths.push((vec![current], false));
+            }
+            SimplePathSegment::LineTo { x, y } => {
+                current = ElementPoint::new(x, y);
+                push_point(&mut subpaths, current);
+            }
+            SimplePathSegment::Quadratic { x1, y1, x, y } => {
+                let (control, end) = (ElementPoint::new(x1, y1), ElementPoint::new(x, y));
+                let segments =
+                    flattening_segments((control - current).length() + (end - control).length());
+                (1..=segments).for_each(|i| {
+                    let t = i as f64 / segments as f64;
+                    let p = current.lerp(control, t).lerp(control.lerp(end, t), t);
+                    push_point(&mut subpaths, p);
+                });
+                current = end;
+            }
+            SimplePathSegment::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+            } => {
+                let (c1, c2, end) = (
+                    ElementPoint::new(x1, y1),
+                    ElementPoint::new(x2, y2),
+                    ElementPoint::new(x, y),
+                );
+                let segments = flattening_segments(
+                    (c1 - current).length() + (c2 - c1).length() + (end - c2).length(),
+                );
+                (1..=segments).for_each(|i| {
+                    let t = i as f64 / segments as f64;
+                    let (a, b, c) = (current.lerp(c1, t), c1.lerp(c2, t), c2.lerp(end, t));
+                    push_point(&mut subpaths, a.lerp(b, t).lerp(b.lerp(c, t), t));
+                });
+                current = end;
+            }
+            SimplePathSegment::ClosePath => {
+                if let Some((subpath, closed)) = subpaths.last_mut() {
+                    *closed = true;
+                    // drawing continues from the start of the closed subpath
+                    current = subpath[0];
+                    subpaths.push((vec![current], false));
+                }
+            }
+        }
+    }
+
+    // open subpaths can't be filled, so they only need an outline if there are any
+    let has_open = subpaths
+        .iter()
+        .any(|(subpath, closed)| !closed && subpath.len() > 1);
+    let outline = match has_open {
+        true => style.outline(node, import),
+        false => None,
+    };
+
+    subpaths.into_iter().for_each(|(subpath, closed)| {
+        let points = subpath
+            .into_iter()
+            .map(|p| style.transform.transform_point(p))
+            .collect();
+        match (closed, outline) {
+            (true, _) => push_polygon(import, points, paint),
+            (false, Some((color, line_width))) => push_path(import, points, color, line_width),
+            (false, None) => {}
+        }
+    });
+}
+
+fn push_point(subpaths: &mut Vec<(Vec<ElementPoint>, bool)>, point: ElementPoint) {
+    match subpaths.last_mut() {
+        Some((subpath, _)) => subpath.push(point),
+        None => subpaths.push((vec![point], false)),
+    }
+}
+
+/// Single points draw nothing on the canvas, so they aren't worth a command.
+fn push_path(import: &mut SvgImport, points: Vec<ElementPoint>, color: Color, line_width: f64) {
+    if points.len() > 1 {
+        import.commands.push(Command::DrawPath(Path {
+            points,
+            color,
+            line_width,
+        }));
+    }
+}
+
+fn push_polygon(import: &mut SvgImport, points: Vec<ElementPoint>, paint: ClosedPaint) {
+    if points.len() > 2 {
+        import.commands.push(Command::DrawPolygon(Polygon {
+            points,
+            color: paint.color,
+            line_width: paint.line_width,
+            fill_color: paint.fill_color,
+            fill_mode: paint.fill_mode,
+        }));
+    } else {
+        push_path(import, points, paint.color, paint.line_width);
+    }
+}
+
+/// Roughly one segment per 3px of curve, enough to look smooth when stroked.
+fn flattening_segments(length: f64) -> usize {
+    ((length / 3.0).ceil() as usize).clamp(8, 128)
+}
+
+/// Maps an `<svg>` element's viewBox onto its width and height.
+fn view_box(node: Node) -> Transform {
+    let Some(view_box) = node
+        .attribute("viewBox")
+        .and_then(|value| svgtypes::ViewBox::from_str(value).ok())
+    else {
+        return Transform::identity();
+    };
+    let width = node
+        .attribute("width")
+        .and_then(length)
+        .unwrap_or(view_box.w);
+    let height = node
+        .attribute("height")
+        .and_then(length)
+        .unwrap_or(view_box.h);
+    Transform::translation(-view_box.x, -view_box.y)
+        .then_scale(width / view_box.w, height / view_box.h)
+}
+
+/// An opacity number, clamped to 0..=1 like browsers do.
+fn opacity(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .map(|opacity| opacity.clamp(0.0, 1.0))
+}
+
+/// A presentation property, with the `style` attribute taking precedence over
+/// the plain attribute like it does in CSS.
+fn property<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attribute("style")
+        .and_then(|style| {
+            style
+                .rsplit(';')
+                .filter_map(|declaration| declaration.split_once(':'))
+                .find(|(key, _)| key.trim() == name)
+                .map(|(_, value)| value.trim())
+        })
+        .or_else(|| node.attribute(name))
+}
+
+/// Lengths are taken as user units; a unit suffix is ignored.
+fn length(value: &str) -> Option<f64> {
+    Length::from_str(value).ok().map(|length| length.number)
+}
+
+fn number(node: Node, name: &str) -> f64 {
+    node.attribute(name).and_then(length).unwrap_or(0.0)
+}
+
+fn point(node: Node, x: &str, y: &str) -> ElementPoint {
+    ElementPoint::new(number(node, x), number(node, y))
+}
+
+fn color_from_svg(color: svgtypes::Color) -> Color {
+    Color(
+        color.red as f64,
+        color.green as f64,
+        color.blue as f64,
+        color.alpha as f64 / 255.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drawing::{Connectivity, FloodFill};
+
+    const RED: Color = Color(255.0, 0.0, 0.0, 1.0);
+    const BLUE: Color = Color(0.0, 0.0, 255.0, 1.0);
+    const BLACK: Color = Color(0.0, 0.0, 0.0, 1.0);
+    const WHITE: Color = Color(255.0, 255.0, 255.0, 1.0);
+
+    fn point(x: f64, y: f64) -> ElementPoint {
+        ElementPoint::new(x, y)
+    }
+
+    fn on_layer(commands: Vec<Command>) -> Vec<LayerCommand> {
+        commands
+            .into_iter()
+            .map(|command| LayerCommand { layer: 0, command })
+            .collect()
+    }
+
+    /// `commands` on a single visible layer of a 4 by 3 drawing.
+    fn export(commands: Vec<Command>) -> String {
+        export_svg(
+            &[Layer::new(0, "Layer 1".to_string())],
+            &on_layer(commands),
+            4.0,
+            3.0,
+        )
+    }
+
+    fn line(start: ElementPoint, end: ElementPoint, color: Color) -> Command {
+        Command::DrawLine(ShapeProperties {
+            start_point: start,
+            end_point: end,
+            color,
+            line_width: 1.0,
+            fill_color: BLUE,
+            fill_mode: FillMode::Stroke,
+        })
+    }
+
+    fn rectangle(fill_mode: FillMode) -> Command {
+        Command::DrawRectangle(ShapeProperties {
+            start_point: point(0.0, 0.0),
+            end_point: point(2.0, 1.0),
+            color: RED,
+            line_width: 1.0,
+            fill_color: BLUE,
+            fill_mode,
+        })
+    }
+
+    fn fill(x: f64, y: f64, color: Color) -> Command {
+        Command::FloodFill(FloodFill {
+            point: point(x, y),
+            color,
+            tolerance: 0,
+            connectivity: Connectivity::Four,
+        })
+    }
+
+    /// Where `part` starts in `svg`, failing with the whole document if it's missing.
+    fn find(svg: &str, part: &str) -> usize {
+        svg.find(part)
+            .unwrap_or_else(|| panic!("no {:?} in\n{}", part, svg))
+    }
+
+    #[test]
+    fn erasing_masks_only_what_was_drawn_before() {
+        let svg = export(vec![
+            line(point(0.0, 0.5), point(4.0, 0.5), RED),
+            Command::ErasePath(Path {
+                points: vec![point(1.0, 0.5), point(2.0, 0.5)],
+                color: RED,
+                line_width: 2.0,
+            }),
+            line(point(0.0, 2.5), point(4.0, 2.5), BLUE),
+        ]);
+        find(
+            &svg,
+            r#"<mask id="erase-1" maskUnits="userSpaceOnUse" x="0" y="0" width="4" height="3"><rect width="4" height="3" fill="white"/><path d="M1 0.5 L2 0.5" fill="none" stroke="black" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"/></mask>"#,
+        );
+        let masked = find(&svg, r#"<g mask="url(#erase-1)">"#);
+        let erased = find(&svg, r#"<line x1="0" y1="0.5""#);
+        let end = masked + find(&svg[masked..], "</g>");
+        let after = find(&svg, r#"<line x1="0" y1="2.5""#);
+        assert!(masked < erased && erased < end && end < after, "{}", svg);
+    }
+
+    #[test]
+    fn erasing_a_single_point_leaves_a_dot() {
+        let svg = export(vec![Command::ErasePath(Path {
+            points: vec![point(1.0, 1.0)],
+            color: RED,
+            line_width: 2.0,
+        })]);
+        find(&svg, r#"<path d="M1 1 L1 1" fill="none" stroke="black""#);
+    }
+
+    #[test]
+    fn shapes_are_painted_by_their_fill_mode() {
+        let svg = export(vec![
+            rectangle(FillMode::Fill),
+            rectangle(FillMode::Stroke),
+            rectangle(FillMode::StrokeAndFill),
+        ]);
+        find(
+            &svg,
+            r#"<rect x="0" y="0" width="2" height="1" fill="rgb(0,0,255)" stroke="none"/>"#,
+        );
+        find(
+            &svg,
+            r#"<rect x="0" y="0" width="2" height="1" fill="none" stroke="rgb(255,0,0)" stroke-width="1"/>"#,
+        );
+        find(
+            &svg,
+            r#"<rect x="0" y="0" width="2" height="1" fill="rgb(0,0,255)" stroke="rgb(255,0,0)" stroke-width="1"/>"#,
+        );
+    }
+
+    #[test]
+    fn alpha_and_layer_opacity_become_attributes() {
+        let mut layers = vec![
+            Layer::new(0, "Faded".to_string()),
+            Layer::new(1, "Hidden".to_string()),
+        ];
+        layers[0].opacity = 0.5;
+        layers[1].visible = false;
+        let history = [
+            LayerCommand {
+                layer: 0,
+                command: line(point(0.0, 0.5), point(4.0, 0.5), RED.with_alpha(0.25)),
+            },
+            LayerCommand {
+                layer: 0,
+                command: fill(0.5, 2.5, BLUE.with_alpha(0.75)),
+            },
+            LayerCommand {
+                layer: 1,
+                command: line(point(0.0, 1.5), point(4.0, 1.5), RED),
+            },
+        ];
+        let svg = export_svg(&layers, &history, 4.0, 3.0);
+        find(
+            &svg,
+            r#"<g inkscape:groupmode="layer" inkscape:label="Faded" opacity="0.5">"#,
+        );
+        find(
+            &svg,
+            r#"stroke="rgb(255,0,0)" stroke-opacity="0.25" stroke-width="1"/>"#,
+        );
+        find(&svg, r#"fill="rgb(0,0,255)" fill-opacity="0.75""#);
+        assert!(!svg.contains("Hidden"), "{}", svg);
+    }
+
+    #[test]
+    fn opaque_colors_leave_out_the_opacity() {
+        let svg = export(vec![rectangle(FillMode::StrokeAndFill)]);
+        assert!(!svg.contains("-opacity"), "{}", svg);
+    }
+
+    #[test]
+    fn each_fill_sees_everything_drawn_before_it() {
+        let svg = export(vec![
+            fill(0.5, 0.5, RED),
+            // a wall drawn after the first fill splits the second one's region
+            line(point(1.5, 0.0), point(1.5, 3.0), BLUE),
+            fill(0.5, 0.5, BLUE),
+            Command::EraseCanvas,
+            line(point(0.0, 1.5), point(4.0, 1.5), RED),
+            fill(0.5, 2.5, BLUE),
+        ]);
+        // only what comes after the clear is exported
+        assert!(!svg.contains("M0 0h1v1h-1z"), "{}", svg);
+        find(
+            &svg,
+            r#"<path d="M0 2h4v1h-4z" fill="rgb(0,0,255)" shape-rendering="crispEdges"/>"#,
+        );
+
+        let svg = export(vec![
+            fill(0.5, 0.5, RED),
+            line(point(1.5, 0.0), point(1.5, 3.0), BLUE),
+            fill(0.5, 0.5, BLUE),
+        ]);
+        find(
+            &svg,
+            r#"<path d="M0 0h4v1h-4z M0 1h4v1h-4z M0 2h4v1h-4z" fill="rgb(255,0,0)""#,
+        );
+        find(
+            &svg,
+            r#"<path d="M0 0h1v1h-1z M0 1h1v1h-1z M0 2h1v1h-1z" fill="rgb(0,0,255)""#,
+        );
+    }
+
+    // IMPORT
+
+    fn import(body: &str) -> SvgImport {
+        import_svg(&format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg">{}</svg>"#,
+            body
+        ))
+        .unwrap()
+    }
+
+    /// The one command `body` imports to, with nothing reported.
+    fn import_one(body: &str) -> Command {
+        let import = import(body);
+        assert!(import.report.is_empty(), "{:?}", import.report);
+        assert_eq!(import.commands.len(), 1, "{:?}", import.commands);
+        import.commands.into_iter().next().unwrap()
+    }
+
+    fn assert_near(actual: ElementPoint, expected: ElementPoint) {
+        assert!(
+            (actual - expected).length() < 1e-9,
+            "{:?} isn't {:?}",
+            actual,
+            expected
+        );
+    }
+
+    fn points(command: Command) -> Vec<ElementPoint> {
+        match command {
+            Command::DrawPath(path) => path.points,
+            Command::DrawPolygon(polygon) => polygon.points,
+            other => panic!("{:?} has no points", other),
+        }
+    }
+
+    #[test]
+    fn line_becomes_a_line() {
+        assert_eq!(
+            import_one(r#"<line x1="1" y1="2" x2="3" y2="4" stroke="red" stroke-width="2"/>"#),
+            Command::DrawLine(ShapeProperties {
+                start_point: point(1.0, 2.0),
+                end_point: point(3.0, 4.0),
+                color: RED,
+                line_width: 2.0,
+                fill_color: WHITE,
+                fill_mode: FillMode::Stroke,
+            })
+        );
+    }
+
+    #[test]
+    fn rect_becomes_a_rectangle() {
+        assert_eq!(
+            import_one(r#"<rect x="1" y="2" width="3" height="4" fill="blue"/>"#),
+            Command::DrawRectangle(ShapeProperties {
+                start_point: point(1.0, 2.0),
+                end_point: point(4.0, 6.0),
+                color: BLUE,
+                line_width: 1.0,
+                fill_color: BLUE,
+                fill_mode: FillMode::Fill,
+            })
+        );
+    }
+
+    #[test]
+    fn transformed_rect_becomes_a_polygon() {
+        let command = import_one(
+            r#"<rect width="2" height="1" transform="rotate(90)" stroke="red" fill="none"/>"#,
+        );
+        let Command::DrawPolygon(Polygon {
+            color, fill_mode, ..
+        }) = &command
+        else {
+            panic!("{:?} isn't a polygon", command);
+        };
+        assert_eq!((*color, *fill_mode), (RED, FillMode::Stroke));
+        let corners = [
+            point(0.0, 0.0),
+            point(0.0, 2.0),
+            point(-1.0, 2.0),
+            point(-1.0, 0.0),
+        ];
+        let points = points(command);
+        assert_eq!(points.len(), corners.len());
+        points
+            .into_iter()
+            .zip(corners)
+            .for_each(|(actual, expected)| assert_near(actual, expected));
+    }
+
+    #[test]
+    fn circle_becomes_a_circle() {
+        assert_eq!(
+            import_one(r#"<circle cx="5" cy="5" r="2" stroke="red" fill="none"/>"#),
+            Command::DrawCircle(ShapeProperties {
+                start_point: point(5.0, 5.0),
+                end_point: point(7.0, 5.0),
+                color: RED,
+                line_width: 1.0,
+                fill_color: WHITE,
+                fill_mode: FillMode::Stroke,
+            })
+        );
+    }
+
+    #[test]
+    fn ellipses_and_squashed_circles_become_polygons() {
+        let ellipse = points(import_one(r#"<ellipse rx="4" ry="2"/>"#));
+        assert_eq!(
+            ellipse.len(),
+            flattening_segments(8.0 * std::f64::consts::PI)
+        );
+        ellipse.iter().for_each(|p| {
+            assert!(
+                (p.x * p.x / 16.0 + p.y * p.y / 4.0 - 1.0).abs() < 1e-9,
+                "{:?}",
+                p
+            )
+        });
+
+        let squashed = points(import_one(
+            r#"<circle r="2" transform="scale(2 1)" fill="blue"/>"#,
+        ));
+        squashed.iter().for_each(|p| {
+            assert!(
+                (p.x * p.x / 16.0 + p.y * p.y / 4.0 - 1.0).abs() < 1e-9,
+                "{:?}",
+                p
+            )
+        });
+    }
+
+    #[test]
+    fn polyline_becomes_a_path_and_polygon_a_polygon() {
+        assert_eq!(
+            import_one(r#"<polyline points="0,0 4,0 4,4" stroke="red" fill="none"/>"#),
+            Command::DrawPath(Path {
+                points: vec![point(0.0, 0.0), point(4.0, 0.0), point(4.0, 4.0)],
+                color: RED,
+                line_width: 1.0,
+            })
+        );
+        assert_eq!(
+            import_one(r#"<polygon points="0,0 4,0 4,4" fill="blue"/>"#),
+            Command::DrawPolygon(Polygon {
+                points: vec![point(0.0, 0.0), point(4.0, 0.0), point(4.0, 4.0)],
+                color: BLUE,
+                line_width: 1.0,
+                fill_color: BLUE,
+                fill_mode: FillMode::Fill,
+            })
+        );
+    }
+
+    #[test]
+    fn path_subpaths_become_polygons_when_closed_and_paths_when_open() {
+        let import = import(r#"<path d="M0 0 L10 0 L10 10 Z M20 0 L30 0" stroke="red"/>"#);
+        assert_eq!(
+            import.commands,
+            [
+                Command::DrawPolygon(Polygon {
+                    points: vec![point(0.0, 0.0), point(10.0, 0.0), point(10.0, 10.0)],
+                    color: RED,
+                    line_width: 1.0,
+                    fill_color: BLACK,
+                    fill_mode: FillMode::StrokeAndFill,
+                }),
+                Command::DrawPath(Path {
+                    points: vec![point(20.0, 0.0), point(30.0, 0.0)],
+                    color: RED,
+                    line_width: 1.0,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn curves_are_flattened_through_their_points() {
+        let quadratic = points(import_one(
+            r#"<path d="M0 0 Q10 10 20 0" stroke="red" fill="none"/>"#,
+        ));
+        let segments = flattening_segments(2.0 * 200f64.sqrt());
+        assert_eq!(quadratic.len(), segments + 1);
+        assert_near(quadratic[segments / 2], point(10.0, 5.0));
+        assert_near(quadratic[segments], point(20.0, 0.0));
+
+        let cubic = points(import_one(
+            r#"<path d="M0 0 C0 10 20 10 20 0" stroke="red" fill="none"/>"#,
+        ));
+        let segments = flattening_segments(40.0);
+        assert_eq!(cubic.len(), segments + 1);
+        assert_near(cubic[segments / 2], point(10.0, 7.5));
+        assert_near(cubic[segments], point(20.0, 0.0));
+    }
+
+    #[test]
+    fn arcs_are_flattened_along_their_circle() {
+        let arc = points(import_one(
+            r#"<path d="M0 0 A10 10 0 0 1 20 0" stroke="red" fill="none"/>"#,
+        ));
+        assert!(arc.len() > 8, "{:?}", arc);
+        assert_near(arc[0], point(0.0, 0.0));
+        assert_near(*arc.last().unwrap(), point(20.0, 0.0));
+        // the curves arcs turn into stay within a hair of the circle
+        arc.iter().for_each(|p| {
+            assert!(
+                ((*p - point(10.0, 0.0)).length() - 10.0).abs() < 0.05,
+                "{:?}",
+                p
+            );
+            assert!(p.y <= 1e-9, "{:?} is on the wrong side", p);
+        });
+    }
+
+    #[test]
+    fn view_box_scales_coordinates_and_widths() {
+        let import = import_svg(concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="100" viewBox="10 0 100 50">"#,
+            r#"<line x1="10" y1="0" x2="110" y2="50" stroke="red" stroke-width="2"/>"#,
+            "</svg>"
+        ))
+        .unwrap();
+        let [Command::DrawLine(line)] = import.commands.as_slice() else {
+            panic!("{:?}", import.commands);
+        };
+        assert_near(line.start_point, point(0.0, 0.0));
+        assert_near(line.end_point, point(200.0, 100.0));
+        assert_eq!(line.line_width, 4.0);
+    }
+
+    #[test]
+    fn styles_cascade_with_the_style_attribute_first() {
+        let body = concat!(
+            r#"<g stroke="red" style="stroke-width: 3" color="lime" stroke-opacity="0.5">"#,
+            r#"<line x2="1" style="stroke: blue" stroke="green"/>"#,
+            r#"<line x2="1" stroke="currentColor"/>"#,
+            "</g>"
+        );
+        let import = import(body);
+        let colors: Vec<(Color, f64)> = import
+            .commands
+            .iter()
+            .map(|command| match command {
+                Command::DrawLine(line) => (line.color, line.line_width),
+                other => panic!("{:?} isn't a line", other),
+            })
+            .collect();
+        assert_eq!(
+            colors,
+            [
+                (Color(0.0, 0.0, 255.0, 0.5), 3.0),
+                (Color(0.0, 255.0, 0.0, 0.5), 3.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn report_lists_what_was_skipped_or_approximated() {
+        let import = import(concat!(
+            "\n",
+            r#"<text>skipped</text>"#,
+            "\n",
+            r#"<path d="M0 0 L10 0 L" stroke="red" fill="none"/>"#,
+            "\n",
+            r#"<rect width="2" height="2" fill="url(#gradient)"/>"#,
+            "\n",
+            r#"<rect width="2" height="2" fill="url(#gradient) red"/>"#,
+            "\n",
+            r#"<rect width="2" height="2" rx="1" fill="none"/>"#,
+        ));
+        assert_eq!(
+            import.report,
+            [
+                "line 2: <text> is not supported, skipped it",
+                "line 3: <path> has malformed path data, imported it up to the error",
+                "line 4: <rect> uses a gradient or pattern, replaced it with a solid color",
+                "line 5: <rect> uses a gradient or pattern, replaced it with a solid color",
+                "line 6: <rect> has neither stroke nor fill, skipped it",
+            ]
+        );
+        // what could be read still comes through
+        assert_eq!(
+            points(import.commands[0].clone()),
+            [point(0.0, 0.0), point(10.0, 0.0)]
+        );
+        let fills: Vec<Color> = import.commands[1..]
+            .iter()
+            .map(|command| match command {
+                Command::DrawRectangle(rectangle) => rectangle.fill_color,
+                other => panic!("{:?} isn't a rectangle", other),
+            })
+            .collect();
+        assert_eq!(fills, [BLACK, RED]);
+        assert!(import.summary().contains("Imported 3 shapes."));
+    }
+}
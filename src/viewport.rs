@@ -0,0 +1,180 @@
+// viewport.rs
+//
+// Where the drawing shows on screen. The drawing is scaled by the zoom about
+// its top left corner, then moved by the pan. Commands and tools work in
+// document points; the mouse gives screen points, measured from the top left
+// corner of the canvas area, and the viewport maps one to the other.
+
+use dioxus::prelude::*;
+use dioxus_elements::geometry::{ElementPoint, ElementSpace};
+use euclid::Vector2D;
+use std::ops::RangeInclusive;
+
+/// The element the drawing is shown in, and screen points are measured from.
+pub const VIEWPORT_ID: &str = "canvas-viewport";
+/// How far the drawing can be zoomed out and in.
+pub const ZOOMS: RangeInclusive<f64> = 0.05..=32.0;
+/// How much zooming in or out by a step changes the zoom.
+pub const ZOOM_STEP: f64 = 1.25;
+/// The most pixels a layer canvas gets. Past that zoom, the layers are drawn
+/// no sharper and the browser scales them up the rest of the way.
+pub const MAX_CANVAS_PIXELS: f64 = 4096.0 * 4096.0;
+/// Empty space kept around the drawing when zooming to fit.
+const FIT_MARGIN: f64 = 20.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    /// Screen pixels per document pixel.
+    pub zoom: f64,
+    /// Where the top left corner of the drawing is on screen.
+    pub pan: Vector2D<f64, ElementSpace>,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            pan: Vector2D::zero(),
+        }
+    }
+}
+
+impl Viewport {
+    pub fn to_document(self, point: ElementPoint) -> ElementPoint {
+        ((point - self.pan).to_vector() / self.zoom).to_point()
+    }
+
+    pub fn to_screen(self, point: ElementPoint) -> ElementPoint {
+        (point.to_vector() * self.zoom + self.pan).to_point()
+    }
+
+    /// Zoomed to `zoom`, keeping what's under `anchor` on screen in place.
+    pub fn zoomed(&self, zoom: f64, anchor: ElementPoint) -> Self {
+        let zoom = zoom.clamp(*ZOOMS.start(), *ZOOMS.end());
+        let fixed = self.to_document(anchor);
+        let zoomed = Self { zoom, ..*self };
+        zoomed.panned(anchor - zoomed.to_screen(fixed))
+    }
+
+    pub fn panned(&self, by: Vector2D<f64, ElementSpace>) -> Self {
+        Self {
+            pan: self.pan + by,
+            ..*self
+        }
+    }
+
+    /// The whole drawing, `width` by `height`, as large as it fits on a
+    /// screen `screen_width` by `screen_height`, centered.
+    pub fn fit(width: f64, height: f64, screen_width: f64, screen_height: f64) -> Self {
+        let zoom = ((screen_width - 2.0 * FIT_MARGIN) / width)
+            .min((screen_height - 2.0 * FIT_MARGIN) / height)
+            .clamp(*ZOOMS.start(), *ZOOMS.end());
+        Self {
+            zoom,
+            pan: euclid::vec2(
+                (screen_width - width * zoom) / 2.0,
+                (screen_height - height * zoom) / 2.0,
+            ),
+        }
+    }
+
+    /// How many canvas pixels each document pixel gets when the drawing,
+    /// `width` by `height`, is shown at this zoom. Powers of two, so the layers
+    /// only need drawing again once the zoom has changed by a lot.
+    pub fn canvas_scale(&self, width: f64, height: f64) -> f64 {
+        let mut scale = 1.0;
+        while scale < self.zoom && width * height * (scale * 2.0).powi(2) <= MAX_CANVAS_PIXELS {
+            scale *= 2.0;
+        }
+        scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64) -> ElementPoint {
+        ElementPoint::new(x, y)
+    }
+
+    fn assert_near(actual: ElementPoint, expected: ElementPoint) {
+        assert!(
+            (actual - expected).length() < 1e-9,
+            "{:?} is not {:?}",
+            actual,
+            expected
+        );
+    }
+
+    fn viewport() -> Viewport {
+        Viewport {
+            zoom: 2.5,
+            pan: euclid::vec2(-40.0, 17.0),
+        }
+    }
+
+    #[test]
+    fn to_document_and_to_screen_are_inverses() {
+        for viewport in [Viewport::default(), viewport()] {
+            for p in [point(0.0, 0.0), point(12.5, -3.0), point(800.0, 600.0)] {
+                assert_near(viewport.to_screen(viewport.to_document(p)), p);
+                assert_near(viewport.to_document(viewport.to_screen(p)), p);
+            }
+        }
+        assert_eq!(viewport().to_screen(point(10.0, 2.0)), point(-15.0, 22.0));
+    }
+
+    #[test]
+    fn zooming_keeps_the_anchor_in_place() {
+        let anchor = point(300.0, 200.0);
+        let under = viewport().to_document(anchor);
+        for zoom in [0.5, 1.0, 4.0, 30.0] {
+            let zoomed = viewport().zoomed(zoom, anchor);
+            assert_eq!(zoomed.zoom, zoom);
+            assert_near(zoomed.to_screen(under), anchor);
+        }
+    }
+
+    #[test]
+    fn zooming_stops_at_the_limits() {
+        let anchor = point(300.0, 200.0);
+        let under = viewport().to_document(anchor);
+        let zoomed = viewport().zoomed(1000.0, anchor);
+        assert_eq!(zoomed.zoom, *ZOOMS.end());
+        assert_near(zoomed.to_screen(under), anchor);
+        assert_eq!(viewport().zoomed(0.0, anchor).zoom, *ZOOMS.start());
+    }
+
+    #[test]
+    fn fit_centers_the_drawing_inside_the_margin() {
+        // wider than the screen, so the width decides the zoom
+        let fitted = Viewport::fit(800.0, 200.0, 440.0, 300.0);
+        assert_eq!(fitted.zoom, 0.5);
+        assert_near(fitted.to_screen(point(0.0, 0.0)), point(20.0, 100.0));
+        assert_near(fitted.to_screen(point(800.0, 200.0)), point(420.0, 200.0));
+
+        // taller, so the height does, and a small drawing is zoomed in
+        let fitted = Viewport::fit(10.0, 20.0, 1000.0, 240.0);
+        assert_eq!(fitted.zoom, 10.0);
+        assert_near(fitted.to_screen(point(0.0, 0.0)), point(450.0, 20.0));
+        assert_near(fitted.to_screen(point(10.0, 20.0)), point(550.0, 220.0));
+    }
+
+    #[test]
+    fn fit_stays_within_the_zooms() {
+        assert_eq!(Viewport::fit(1.0, 1.0, 2000.0, 2000.0).zoom, *ZOOMS.end());
+        assert_eq!(Viewport::fit(1e6, 1e6, 400.0, 400.0).zoom, *ZOOMS.start());
+    }
+
+    #[test]
+    fn canvas_scale_is_a_power_of_two_up_to_the_pixel_limit() {
+        let at = |zoom| Viewport { zoom, ..viewport() };
+        assert_eq!(at(0.5).canvas_scale(800.0, 600.0), 1.0);
+        assert_eq!(at(1.0).canvas_scale(800.0, 600.0), 1.0);
+        assert_eq!(at(1.5).canvas_scale(800.0, 600.0), 2.0);
+        assert_eq!(at(3.0).canvas_scale(800.0, 600.0), 4.0);
+        // 8 would be 6400 by 4800, past the limit
+        assert_eq!(at(32.0).canvas_scale(800.0, 600.0), 4.0);
+    }
+}